@@ -3,11 +3,12 @@ extern crate criterion;
 
 use witchbooru::{
     image,
-    models::{NaiveBayes, NeuralNet},
-    Classifier, Params,
+    models::{NaiveBayes, NeuralNet, Preprocessor},
+    ndarray::ArrayView1,
+    Classifier, Params, PixelFormat,
 };
 
-use criterion::{BatchSize, Criterion};
+use criterion::Criterion;
 use std::{
     fs::File,
     io::{BufRead, BufReader},
@@ -18,27 +19,189 @@ fn predict(c: &mut Criterion) {
     let params = Params {
         neural_net: NeuralNet::new(File::open("model/neural-net.onnx").unwrap()).unwrap(),
         naive_bayes: NaiveBayes::new(File::open("model/naive-bayes.npz").unwrap()).unwrap(),
+        calibration: None,
         general_tags: read_list("model/general-tags.txt").unwrap(),
         character_tags: read_list("model/character-tags.txt").unwrap(),
         topk: 20,
+        augmentation: None,
     };
     let classifier = Classifier::new(params).unwrap();
 
     let img = image::open("imgs/img.jpg").unwrap();
-    criterion::black_box(classifier.predict(img.clone()).unwrap());
+    criterion::black_box(classifier.predict_ref(&img).unwrap());
 
     c.bench_function("predict", |b| {
-        b.iter_batched(
-            || img.clone(),
-            |img| classifier.predict(img).unwrap(),
-            BatchSize::SmallInput,
-        )
+        b.iter(|| classifier.predict_ref(&img).unwrap())
     });
+
+    let rgb = img.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    c.bench_function("predict_rgb", |b| {
+        b.iter(|| {
+            classifier
+                .predict_rgb(&rgb, width, height, PixelFormat::Rgb)
+                .unwrap()
+        })
+    });
+}
+
+fn stages(c: &mut Criterion) {
+    let neural_net = NeuralNet::new(File::open("model/neural-net.onnx").unwrap()).unwrap();
+    let naive_bayes = NaiveBayes::new(File::open("model/naive-bayes.npz").unwrap()).unwrap();
+    let num_general_tags = read_list("model/general-tags.txt").unwrap().len();
+
+    let img = image::open("imgs/img.jpg").unwrap();
+    let large_img = img.resize_exact(4032, 3024, image::imageops::FilterType::Triangle);
+
+    let mut preprocessor = Preprocessor::new();
+    let tensor = preprocessor.preprocess(&img).unwrap().to_owned();
+    let output = neural_net.backend().run(tensor.view()).unwrap();
+    let probs = ArrayView1::from(&output[0].as_slice().unwrap()[..num_general_tags]);
+
+    let mut group = c.benchmark_group("stages");
+    group.bench_function("preprocess", |b| {
+        b.iter(|| {
+            preprocessor.preprocess(&img).unwrap();
+        })
+    });
+    group.bench_function("preprocess_large", |b| {
+        b.iter(|| {
+            preprocessor.preprocess(&large_img).unwrap();
+        })
+    });
+    group.bench_function("network", |b| {
+        b.iter(|| neural_net.backend().run(tensor.view()).unwrap())
+    });
+    group.bench_function("head", |b| b.iter(|| naive_bayes.predict(probs)));
+    group.finish();
+}
+
+fn neural_net(c: &mut Criterion) {
+    const QUANTIZED_PATH: &str = "model/neural-net.int8.onnx";
+    if !Path::new(QUANTIZED_PATH).exists() {
+        return;
+    }
+
+    let float = NeuralNet::new(File::open("model/neural-net.onnx").unwrap()).unwrap();
+    let quantized = NeuralNet::new(File::open(QUANTIZED_PATH).unwrap()).unwrap();
+    let num_general_tags = read_list("model/general-tags.txt").unwrap().len();
+
+    let img = image::open("imgs/img.jpg").unwrap();
+
+    // agreement of general tags compared to float model
+    const TOPK: usize = 20;
+    let float_output = float.predict(&img).unwrap();
+    let quantized_output = quantized.predict(&img).unwrap();
+    let float_probs = &float_output.as_slice().unwrap()[..num_general_tags];
+    let quantized_probs = &quantized_output.as_slice().unwrap()[..num_general_tags];
+    let max_error = float_probs
+        .iter()
+        .zip(quantized_probs)
+        .fold(0f32, |m, (x, y)| m.max((x - y).abs()));
+    let top_float = top_indices(float_probs, TOPK);
+    let top_quantized = top_indices(quantized_probs, TOPK);
+    let num_agreed = top_float
+        .iter()
+        .filter(|i| top_quantized.contains(*i))
+        .count();
+    println!(
+        "quantized neural net: max probability error {:.4}, top-{} agreement {}/{}",
+        max_error, TOPK, num_agreed, TOPK
+    );
+
+    let mut group = c.benchmark_group("neural_net");
+    group.bench_function("float", |b| b.iter(|| float.predict(&img).unwrap()));
+    group.bench_function("quantized", |b| b.iter(|| quantized.predict(&img).unwrap()));
+    group.finish();
+}
+
+#[cfg(feature = "onnxruntime")]
+fn backend(c: &mut Criterion) {
+    use witchbooru::backend::OnnxRuntimeBackend;
+
+    let bytes = std::fs::read("model/neural-net.onnx").unwrap();
+    let tract = NeuralNet::new(bytes.as_slice()).unwrap();
+    let onnxruntime = NeuralNet::with_backend(OnnxRuntimeBackend::new(&bytes, 0).unwrap());
+
+    let img = image::open("imgs/img.jpg").unwrap();
+
+    // agreement of outputs compared to tract
+    let tract_output = tract.predict(&img).unwrap();
+    let onnxruntime_output = onnxruntime.predict(&img).unwrap();
+    let max_error = tract_output
+        .iter()
+        .zip(onnxruntime_output.iter())
+        .fold(0f32, |m, (x, y)| m.max((x - y).abs()));
+    println!(
+        "onnxruntime backend: max probability error {:.4}",
+        max_error
+    );
+
+    let mut group = c.benchmark_group("backend");
+    group.bench_function("tract", |b| b.iter(|| tract.predict(&img).unwrap()));
+    group.bench_function("onnxruntime", |b| {
+        b.iter(|| onnxruntime.predict(&img).unwrap())
+    });
+    group.finish();
+}
+
+fn naive_bayes(c: &mut Criterion) {
+    let neural_net = NeuralNet::new(File::open("model/neural-net.onnx").unwrap()).unwrap();
+    let dense = NaiveBayes::new(File::open("model/naive-bayes.npz").unwrap()).unwrap();
+    let num_general_tags = read_list("model/general-tags.txt").unwrap().len();
+
+    let mut bin = Vec::new();
+    dense.write_quantized(&mut bin).unwrap();
+    let quantized = NaiveBayes::from_quantized(bin).unwrap();
+
+    const SPARSE_CUTOFF: f32 = 0.01;
+    let sparse = NaiveBayes::new(File::open("model/naive-bayes.npz").unwrap())
+        .unwrap()
+        .with_sparse_cutoff(SPARSE_CUTOFF);
+
+    let output = neural_net
+        .predict(&image::open("imgs/img.jpg").unwrap())
+        .unwrap();
+    let probs = ArrayView1::from(&output.as_slice().unwrap()[..num_general_tags]);
+
+    // accuracy compared to dense model
+    let dense_logits = dense.predict(probs);
+    for (name, model) in &[("quantized", &quantized), ("sparse", &sparse)] {
+        const TOPK: usize = 20;
+        let logits = model.predict(probs);
+        let max_error = dense_logits
+            .iter()
+            .zip(logits.iter())
+            .fold(0f32, |m, (x, y)| m.max((x - y).abs()));
+        let top_dense = top_indices(dense_logits.as_slice().unwrap(), TOPK);
+        let top = top_indices(logits.as_slice().unwrap(), TOPK);
+        let num_agreed = top_dense.iter().filter(|i| top.contains(*i)).count();
+        println!(
+            "{} naive bayes: max logit error {:.4}, top-{} agreement {}/{}",
+            name, max_error, TOPK, num_agreed, TOPK
+        );
+    }
+
+    let mut group = c.benchmark_group("naive_bayes");
+    group.bench_function("dense", |b| b.iter(|| dense.predict(probs)));
+    group.bench_function("quantized", |b| b.iter(|| quantized.predict(probs)));
+    group.bench_function("sparse", |b| b.iter(|| sparse.predict(probs)));
+    group.finish();
+}
+
+fn top_indices(scores: &[f32], k: usize) -> Vec<usize> {
+    let mut indices: Vec<_> = (0..scores.len()).collect();
+    indices.sort_by(|&i, &j| scores[j].partial_cmp(&scores[i]).unwrap());
+    indices.truncate(k);
+    indices
 }
 
 fn read_list<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<String>> {
     BufReader::new(File::open(path)?).lines().collect()
 }
 
-criterion_group!(benches, predict);
+#[cfg(not(feature = "onnxruntime"))]
+criterion_group!(benches, predict, stages, neural_net, naive_bayes);
+#[cfg(feature = "onnxruntime")]
+criterion_group!(benches, predict, stages, neural_net, backend, naive_bayes);
 criterion_main!(benches);
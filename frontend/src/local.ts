@@ -0,0 +1,92 @@
+import { baseroute } from './baseroute';
+
+// model files are fetched from here, and images are classified in the browser when it is set
+export const modelUrl = process.env.PREACT_APP_MODEL_URL;
+
+const TOPK = 20;
+
+type Tag = {
+    name: string;
+    score: number;
+};
+
+type Prediction = {
+    general: Tag[];
+    character: Tag[];
+};
+
+type Classifier = {
+    predict(img: ImageData): Prediction;
+};
+
+let classifier: Promise<Classifier> | null = null;
+
+export async function predictLocally(file: File): Promise<Prediction> {
+    if (!classifier) {
+        classifier = loadClassifier();
+        // retry on the next image instead of keeping the failure
+        classifier.catch(() => (classifier = null));
+    }
+    const [loaded, img] = await Promise.all([classifier, decodeImage(file)]);
+    return loaded.predict(img);
+}
+
+async function loadClassifier(): Promise<Classifier> {
+    // built by wasm-pack into assets, outside of the webpack bundle
+    const wasm = await import(
+        /* webpackIgnore: true */ `${baseroute}/assets/wasm/witchbooru_wasm.js`
+    );
+    await wasm.default();
+
+    const [neuralNet, naiveBayes, generalTags, characterTags, calibration] =
+        await Promise.all([
+            fetchModelFile('neural-net.nnef.tar').then((r) => r.arrayBuffer()),
+            fetchModelFile('naive-bayes.q8.bin').then((r) => r.arrayBuffer()),
+            fetchModelFile('general-tags.txt').then((r) => r.text()),
+            fetchModelFile('character-tags.txt').then((r) => r.text()),
+            fetchModelFile('calibration.npz', true).then(
+                (r) => r && r.arrayBuffer()
+            ),
+        ]);
+    return new wasm.Classifier(
+        neuralNet,
+        naiveBayes,
+        generalTags,
+        characterTags,
+        calibration || undefined,
+        TOPK
+    );
+}
+
+async function fetchModelFile(name: string): Promise<Response>;
+async function fetchModelFile(
+    name: string,
+    optional: true
+): Promise<Response | null>;
+async function fetchModelFile(
+    name: string,
+    optional = false
+): Promise<Response | null> {
+    const response = await fetch(`${modelUrl}/${name}`);
+    if (response.ok) {
+        return response;
+    }
+    if (optional && response.status === 404) {
+        return null;
+    }
+    throw new Error(`Failed to fetch ${name}`);
+}
+
+async function decodeImage(file: File): Promise<ImageData> {
+    const bitmap = await createImageBitmap(file);
+    const canvas = document.createElement('canvas');
+    canvas.width = bitmap.width;
+    canvas.height = bitmap.height;
+    const ctx = canvas.getContext('2d');
+    if (!ctx) {
+        throw new Error('Canvas is not supported');
+    }
+    ctx.drawImage(bitmap, 0, 0);
+    bitmap.close();
+    return ctx.getImageData(0, 0, canvas.width, canvas.height);
+}
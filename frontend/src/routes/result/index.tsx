@@ -12,6 +12,7 @@ import {
     Progress,
 } from 'preact-bulma';
 import { baseroute } from '../../baseroute';
+import { modelUrl, predictLocally } from '../../local';
 import styles from './style.css';
 
 type RequestData = {
@@ -68,7 +69,20 @@ const Result: FunctionalComponent = () => {
         const controller = new AbortController();
         const signal = controller.signal;
 
-        if (!getState().responseData) {
+        if (!getState().responseData && modelUrl && requestData.file) {
+            // images from URLs are left to the API as they can't be read across origins
+            predictLocally(requestData.file)
+                .then((prediction) => {
+                    if (!signal.aborted) {
+                        setResponseData({ ok: true, ...prediction });
+                    }
+                })
+                .catch((err: Error) => {
+                    if (!signal.aborted) {
+                        setResponseData({ ok: false, error: err.message });
+                    }
+                });
+        } else if (!getState().responseData) {
             const body = new FormData();
             if (requestData.file) {
                 body.append('file', requestData.file as Blob);
@@ -0,0 +1,140 @@
+import os
+import numpy as np
+from PIL import Image
+import argparse
+
+WIDTH = 512
+HEIGHT = 512
+
+
+def load_image(filename: str) -> np.ndarray:
+    # mimics Preprocessor of the Rust implementation:
+    # resize preserving aspect ratio, then pad by repeating edge pixels
+    img = Image.open(filename).convert('RGB')
+    scale = min(WIDTH / img.width, HEIGHT / img.height)
+    w = max(1, round(img.width * scale))
+    h = max(1, round(img.height * scale))
+    img = np.asarray(img.resize((w, h), Image.BICUBIC), dtype=np.float32)
+
+    pad_x = WIDTH - w
+    pad_y = HEIGHT - h
+    img = np.pad(img, ((pad_y // 2, pad_y - pad_y // 2),
+                       (pad_x // 2, pad_x - pad_x // 2), (0, 0)), mode='edge')
+    return img / 255
+
+
+def sigmoid(x: np.ndarray) -> np.ndarray:
+    return 1 / (1 + np.exp(-x))
+
+
+def fit_temperature(logits: np.ndarray, labels: np.ndarray,
+                    iterations: int) -> float:
+    # Newton's method on s = 1 / temperature
+    s = 1.0
+    for _ in range(iterations):
+        p = sigmoid(s * logits)
+        grad = np.sum((p - labels) * logits)
+        hess = np.sum(p * (1 - p) * logits ** 2)
+        s -= grad / max(hess, 1e-12)
+    return 1 / s
+
+
+def fit_platt(logits: np.ndarray, labels: np.ndarray, iterations: int,
+              regularization: float) -> tuple[np.ndarray, np.ndarray]:
+    # Newton's method on each character independently.
+    # L2 regularization pulls (a, b) towards identity (1, 0) so that
+    # characters with few examples in the held-out set stay sensible.
+    num_characters = logits.shape[1]
+    a = np.ones(num_characters)
+    b = np.zeros(num_characters)
+    for _ in range(iterations):
+        p = sigmoid(a * logits + b)
+        r = p - labels
+        w = p * (1 - p)
+
+        grad_a = np.sum(r * logits, axis=0) + regularization * (a - 1)
+        grad_b = np.sum(r, axis=0) + regularization * b
+        h_aa = np.sum(w * logits ** 2, axis=0) + regularization
+        h_ab = np.sum(w * logits, axis=0)
+        h_bb = np.sum(w, axis=0) + regularization
+
+        det = h_aa * h_bb - h_ab ** 2
+        det = np.where(np.abs(det) < 1e-12, 1e-12, det)
+        a -= (h_bb * grad_a - h_ab * grad_b) / det
+        b -= (h_aa * grad_b - h_ab * grad_a) / det
+    return a, b
+
+
+def main(args: argparse.Namespace):
+    characters = open(args.character, 'r',
+                      encoding='utf-8').read().splitlines()
+    character_ids = {x: i for (i, x) in enumerate(characters)}
+
+    filenames = []
+    labels = []
+    for line in open(args.labels, 'r', encoding='utf-8'):
+        line = line.rstrip('\n')
+        if not line:
+            continue
+        filename, tags = line.split('\t', 1)
+        label = np.zeros(len(characters), dtype=np.float32)
+        for tag in tags.split():
+            if tag in character_ids:
+                label[character_ids[tag]] = 1
+        filenames.append(os.path.join(os.path.dirname(args.labels), filename))
+        labels.append(label)
+    labels = np.stack(labels)
+
+    naive_bayes = np.load(args.naive_bayes)
+    a, b = naive_bayes['a'], naive_bayes['b']
+
+    logits = []
+    if args.witchbooru:
+        # run the Rust implementation through the Python bindings
+        import witchbooru_py
+        classifier = witchbooru_py.Classifier(args.witchbooru)
+        for filename in filenames:
+            probs = classifier.general_tag_probs(filename)
+            logits.append((probs @ a + b)[np.newaxis])
+    else:
+        import tensorflow as tf
+        model = tf.keras.models.load_model(args.model, compile=False)
+        for i in range(0, len(filenames), args.batch_size):
+            batch = np.stack([load_image(filename)
+                              for filename in filenames[i:i + args.batch_size]])
+            probs = model.predict(batch)[:, :a.shape[0]]
+            logits.append(probs @ a + b)
+    logits = np.concatenate(logits).astype(np.float64)
+
+    if args.method == 'temperature':
+        temperature = fit_temperature(logits, labels, args.iterations)
+        print(f'temperature: {temperature}')
+        np.savez(args.output, temperature=np.float32(temperature))
+    else:
+        a, b = fit_platt(logits, labels, args.iterations, args.regularization)
+        np.savez(args.output, a=a.astype(np.float32), b=b.astype(np.float32))
+
+
+if __name__ == '__main__':
+    parser = argparse.ArgumentParser()
+    parser.add_argument('labels',
+                        help='TSV file of image paths and space-separated characters')
+    source = parser.add_mutually_exclusive_group(required=True)
+    source.add_argument('--model',
+                        help='DeepDanbooru model in Keras H5 format')
+    source.add_argument('--witchbooru',
+                        help='Model directory to run with witchbooru_py')
+    parser.add_argument('-n', '--naive-bayes', required=True,
+                        help='Trained naive Bayes model')
+    parser.add_argument('-c', '--character', required=True,
+                        help='File containing list of characters')
+    parser.add_argument('--method', choices=['temperature', 'platt'],
+                        default='temperature')
+    parser.add_argument('-r', '--regularization', type=float, default=1.0,
+                        help='L2 regularization strength for Platt scaling')
+    parser.add_argument('-i', '--iterations', type=int, default=50)
+    parser.add_argument('-b', '--batch-size', type=int, default=16)
+    parser.add_argument('-o', '--output', required=True)
+    args = parser.parse_args()
+
+    main(args)
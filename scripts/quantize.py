@@ -0,0 +1,58 @@
+import os
+import numpy as np
+import onnx
+from onnxruntime.quantization import (CalibrationDataReader, QuantFormat,
+                                      QuantType, quantize_dynamic,
+                                      quantize_static)
+from calibrate import load_image
+import argparse
+
+
+class ImageDataReader(CalibrationDataReader):
+    def __init__(self, input_name: str, filenames: list[str]):
+        self.input_name = input_name
+        self.filenames = iter(filenames)
+
+    def get_next(self):
+        filename = next(self.filenames, None)
+        if filename is None:
+            return None
+
+        # NHWC -> NCHW, as fed by NeuralNet::predict()
+        img = load_image(filename).transpose(2, 0, 1)[None]
+        return {self.input_name: img.astype(np.float32)}
+
+
+def main(args: argparse.Namespace):
+    if args.mode == 'dynamic':
+        quantize_dynamic(args.model, args.output,
+                         weight_type=QuantType.QUInt8)
+        return
+
+    if not args.calibration_dir:
+        raise ValueError('--calibration-dir is required for static quantization')
+
+    model = onnx.load(args.model)
+    input_name = model.graph.input[0].name
+    filenames = sorted(os.path.join(args.calibration_dir, filename)
+                       for filename in os.listdir(args.calibration_dir))
+    reader = ImageDataReader(input_name, filenames)
+
+    # QOperator format produces QLinearConv / QLinearMatMul
+    quantize_static(args.model, args.output, reader,
+                    quant_format=QuantFormat.QOperator,
+                    activation_type=QuantType.QUInt8,
+                    weight_type=QuantType.QUInt8)
+
+
+if __name__ == '__main__':
+    parser = argparse.ArgumentParser()
+    parser.add_argument('model', help='Model in ONNX format')
+    parser.add_argument('--mode', choices=['static', 'dynamic'],
+                        default='static')
+    parser.add_argument('-c', '--calibration-dir',
+                        help='Directory containing images for static quantization')
+    parser.add_argument('-o', '--output', required=True)
+    args = parser.parse_args()
+
+    main(args)
@@ -0,0 +1,94 @@
+use crate::{classifier::Scores, pixels::ImageRef, Classifier, Error, Prediction, Result};
+
+use crossbeam_channel::Sender;
+use image::DynamicImage;
+use std::{sync::Arc, thread};
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
+
+/// Wrapper of [`Classifier`] running predictions on its own threads
+/// so that they do not block async executors.
+///
+/// At most `num_threads` images are held by the workers at once, and
+/// further calls of [`AsyncClassifier::predict`] wait for one of them to finish.
+///
+/// Dropping it does not wait for the workers, so it never blocks the executor.
+/// They are detached and exit in the background once the predictions in progress finish.
+pub struct AsyncClassifier {
+    classifier: Arc<Classifier>,
+    permits: Arc<Semaphore>,
+    // dropping the sender disconnects the channel, which stops the workers
+    jobs: Sender<Job>,
+}
+
+struct Job {
+    img: DynamicImage,
+    sender: oneshot::Sender<Result<Scores>>,
+
+    // released when the worker is done with the image
+    _permit: OwnedSemaphorePermit,
+
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl AsyncClassifier {
+    pub fn new(classifier: Classifier, num_threads: usize) -> Self {
+        let num_threads = num_threads.max(1);
+        let classifier = Arc::new(classifier);
+        let (sender, receiver) = crossbeam_channel::unbounded::<Job>();
+
+        for _ in 0..num_threads {
+            let classifier = classifier.clone();
+            let receiver = receiver.clone();
+            thread::spawn(move || {
+                for job in receiver {
+                    // the caller has dropped the future
+                    if job.sender.is_closed() {
+                        continue;
+                    }
+
+                    #[cfg(feature = "tracing")]
+                    let _span = job.span.enter();
+                    let scores = classifier.scores(&ImageRef::Dynamic(&job.img));
+                    let _ = job.sender.send(scores);
+                }
+            });
+        }
+
+        Self {
+            classifier,
+            permits: Arc::new(Semaphore::new(num_threads)),
+            jobs: sender,
+        }
+    }
+
+    /// Predicts tags on a worker thread.
+    ///
+    /// Dropping the future before it completes frees the worker as soon as possible:
+    /// the image is discarded if its prediction has not started yet.
+    pub async fn predict(&self, img: DynamicImage) -> Result<Prediction<'_>> {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Semaphore should not be closed");
+
+        let (sender, receiver) = oneshot::channel();
+        let job = Job {
+            img,
+            sender,
+            _permit: permit,
+            #[cfg(feature = "tracing")]
+            span: tracing::Span::current(),
+        };
+        self.jobs.send(job).map_err(|_| Error::WorkerPanicked)?;
+
+        let scores = receiver.await.map_err(|_| Error::WorkerPanicked)??;
+        Ok(self.classifier.prediction(scores))
+    }
+
+    pub fn classifier(&self) -> &Classifier {
+        &self.classifier
+    }
+}
@@ -0,0 +1,101 @@
+use crate::pixels::ImageRef;
+
+use image::DynamicImage;
+use serde::Serialize;
+use std::fmt;
+
+pub struct Augmentation {
+    pub flip: bool,
+    pub crop_ratio: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum View {
+    Original,
+    Flipped,
+    CenterCrop,
+    TopLeftCrop,
+    TopRightCrop,
+    BottomLeftCrop,
+    BottomRightCrop,
+}
+
+impl fmt::Display for View {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Original => "original",
+            Self::Flipped => "horizontally flipped",
+            Self::CenterCrop => "center crop",
+            Self::TopLeftCrop => "top-left crop",
+            Self::TopRightCrop => "top-right crop",
+            Self::BottomLeftCrop => "bottom-left crop",
+            Self::BottomRightCrop => "bottom-right crop",
+        })
+    }
+}
+
+const CROPS: [View; 5] = [
+    View::CenterCrop,
+    View::TopLeftCrop,
+    View::TopRightCrop,
+    View::BottomLeftCrop,
+    View::BottomRightCrop,
+];
+
+impl Augmentation {
+    /// Whether `ratio` can be used as the crop ratio, that is within `(0, 1]`.
+    pub fn is_valid_crop_ratio(ratio: f32) -> bool {
+        ratio > 0. && ratio <= 1.
+    }
+
+    /// [`View::Original`] followed by the views produced by [`Augmentation::views`],
+    /// in the same order.
+    #[cfg(feature = "cache")]
+    pub(crate) fn view_list(&self) -> Vec<View> {
+        std::iter::once(View::Original)
+            .chain(self.augmented_views())
+            .collect()
+    }
+
+    /// Produces the views other than [`View::Original`], which is left to the caller
+    /// so that the image is not copied.
+    pub(crate) fn views(&self, img: &ImageRef) -> Vec<(View, DynamicImage)> {
+        self.augmented_views()
+            .map(|view| (view, self.view(view, img)))
+            .collect()
+    }
+
+    fn augmented_views(&self) -> impl Iterator<Item = View> {
+        let flipped = Some(View::Flipped).filter(|_| self.flip);
+        let crops: &[View] = if self.crop_ratio.is_some() {
+            &CROPS
+        } else {
+            &[]
+        };
+        flipped.into_iter().chain(crops.iter().copied())
+    }
+
+    fn view(&self, view: View, img: &ImageRef) -> DynamicImage {
+        if view == View::Flipped {
+            return img.fliph();
+        }
+
+        // crops are only produced when the ratio is set
+        let ratio = self.crop_ratio.unwrap_or(1.);
+        let (w, h) = img.dimensions();
+        let (cw, ch) = (
+            ((w as f32 * ratio).round() as u32).clamp(1, w),
+            ((h as f32 * ratio).round() as u32).clamp(1, h),
+        );
+        let (right, bottom) = (w - cw, h - ch);
+        let (x, y) = match view {
+            View::TopLeftCrop => (0, 0),
+            View::TopRightCrop => (right, 0),
+            View::BottomLeftCrop => (0, bottom),
+            View::BottomRightCrop => (right, bottom),
+            View::CenterCrop | View::Original | View::Flipped => (right / 2, bottom / 2),
+        };
+        img.crop_imm(x, y, cw, ch)
+    }
+}
@@ -0,0 +1,23 @@
+#[cfg(feature = "onnxruntime")]
+mod onnxruntime;
+mod tract;
+
+#[cfg(feature = "onnxruntime")]
+pub use self::onnxruntime::OnnxRuntimeBackend;
+pub use self::tract::TractBackend;
+
+use crate::Result;
+
+use tract_onnx::tract_core::ndarray::{ArrayD, ArrayView4};
+
+/// Runtime executing the neural net.
+pub trait InferenceBackend: Send + Sync {
+    /// Runs the neural net on an input of shape `(1, channels, height, width)`.
+    ///
+    /// The input is borrowed from a buffer reused across predictions, so backends
+    /// which need ownership of it have to copy it.
+    ///
+    /// The first output is the probabilities of general tags, and the second one,
+    /// if any, is the embedding.
+    fn run(&self, input: ArrayView4<f32>) -> Result<Vec<ArrayD<f32>>>;
+}
@@ -0,0 +1,63 @@
+use super::InferenceBackend;
+use crate::Result;
+
+use once_cell::sync::OnceCell;
+use onnxruntime::{
+    environment::Environment, ndarray as ort_ndarray, session::Session, tensor::OrtOwnedTensor,
+    GraphOptimizationLevel, LoggingLevel,
+};
+use std::sync::Mutex;
+use tract_onnx::tract_core::ndarray::{ArrayD, ArrayView4, IxDyn};
+
+static ENVIRONMENT: OnceCell<Environment> = OnceCell::new();
+
+/// Backend using ONNX Runtime.
+///
+/// Unlike [`TractBackend`](super::TractBackend), inferences are serialized
+/// because a session cannot be run concurrently.
+pub struct OnnxRuntimeBackend {
+    session: Mutex<Session<'static>>,
+}
+
+impl OnnxRuntimeBackend {
+    pub fn new(model: &[u8], num_threads: i16) -> Result<Self> {
+        // a session borrows the environment, which is shared by all sessions in the process
+        let environment = ENVIRONMENT.get_or_try_init(|| {
+            Environment::builder()
+                .with_name("witchbooru")
+                .with_log_level(LoggingLevel::Warning)
+                .build()
+        })?;
+
+        let session = environment
+            .new_session_builder()?
+            .with_optimization_level(GraphOptimizationLevel::All)?
+            .with_number_threads(num_threads)?
+            .with_model_from_memory(model)?;
+
+        Ok(Self {
+            session: Mutex::new(session),
+        })
+    }
+}
+
+impl InferenceBackend for OnnxRuntimeBackend {
+    fn run(&self, input: ArrayView4<f32>) -> Result<Vec<ArrayD<f32>>> {
+        // convert through raw buffers as the ndarray version may differ from the one of tract
+        let shape = input.dim();
+        let input = ort_ndarray::Array4::from_shape_vec(shape, input.iter().copied().collect())
+            .expect("Input shape should be consistent");
+
+        let mut session = self.session.lock().unwrap();
+        let outputs: Vec<OrtOwnedTensor<f32, _>> = session.run(vec![input])?;
+
+        outputs
+            .iter()
+            .map(|output| {
+                let shape = output.shape().to_vec();
+                let data = output.iter().copied().collect();
+                Ok(ArrayD::from_shape_vec(IxDyn(&shape), data)?)
+            })
+            .collect()
+    }
+}
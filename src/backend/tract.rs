@@ -0,0 +1,97 @@
+use super::InferenceBackend;
+use crate::Result;
+
+use std::io::{Read, Write};
+use tract_onnx::{
+    prelude::*,
+    tract_core::ndarray::{ArrayD, ArrayView4},
+    WithOnnx,
+};
+
+type TractModel = RunnableModel<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>;
+
+pub struct TractBackend {
+    model: TractModel,
+}
+
+impl TractBackend {
+    /// Loads an ONNX model whose input has the given shape.
+    ///
+    /// If `embedding_node` is given, the tensor of the node is added to the outputs.
+    pub fn new<R: Read>(
+        reader: R,
+        input_shape: [usize; 4],
+        embedding_node: Option<&str>,
+    ) -> Result<Self> {
+        Self::from_typed(parse_onnx(reader, input_shape, embedding_node)?)
+    }
+
+    /// Loads a model compiled with [`TractBackend::compile`].
+    ///
+    /// This is faster than loading an ONNX model because parsing, type inference and
+    /// decluttering of the graph are already done.
+    pub fn from_compiled<R: Read>(mut reader: R) -> Result<Self> {
+        let model = tract_nnef::nnef()
+            .with_tract_core()
+            .with_onnx()
+            .model_for_read(&mut reader)?;
+        Self::from_typed(model)
+    }
+
+    /// Converts an ONNX model into an NNEF archive of the decluttered graph,
+    /// which can be loaded with [`TractBackend::from_compiled`].
+    pub fn compile<R: Read, W: Write>(
+        reader: R,
+        writer: W,
+        input_shape: [usize; 4],
+        embedding_node: Option<&str>,
+    ) -> Result<()> {
+        let model = parse_onnx(reader, input_shape, embedding_node)?;
+        tract_nnef::nnef()
+            .with_tract_core()
+            .with_onnx()
+            .write_to_tar(&model, writer)?;
+        Ok(())
+    }
+
+    fn from_typed(model: TypedModel) -> Result<Self> {
+        let model = model.into_optimized()?.into_runnable()?;
+        Ok(Self { model })
+    }
+}
+
+impl InferenceBackend for TractBackend {
+    fn run(&self, input: ArrayView4<f32>) -> Result<Vec<ArrayD<f32>>> {
+        // tract consumes its inputs
+        self.model
+            .run(tvec!(input.to_owned().into()))?
+            .iter()
+            .map(|output| Ok(output.to_array_view::<f32>()?.to_owned()))
+            .collect()
+    }
+}
+
+fn parse_onnx<R: Read>(
+    mut reader: R,
+    input_shape: [usize; 4],
+    embedding_node: Option<&str>,
+) -> Result<TypedModel> {
+    let mut model = tract_onnx::onnx().model_for_read(&mut reader)?;
+    if let Some(name) = embedding_node {
+        let output = model.output_outlets()?[0];
+        let embedding = OutletId::new(model.node_by_name(name)?.id, 0);
+        model.set_output_outlets(&[output, embedding])?;
+    }
+
+    let model = model
+        .with_input_fact(
+            0,
+            InferenceFact::dt_shape(
+                f32::datum_type(),
+                input_shape.iter().copied().collect::<TVec<_>>(),
+            ),
+        )?
+        .into_typed()?
+        .into_decluttered()?;
+    Ok(model)
+}
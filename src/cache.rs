@@ -0,0 +1,131 @@
+use crate::{pixels::ImageRef, Augmentation, Result};
+
+use std::path::Path;
+
+const MODEL_VERSION_KEY: &[u8] = b"model_version";
+
+/// On-disk cache of neural net outputs keyed by hash of decoded image content.
+///
+/// The cache is cleared when it is opened with a model version different from the one
+/// it was populated with.
+pub struct Cache {
+    db: sled::Db,
+    entries: sled::Tree,
+}
+
+pub(crate) struct Entry {
+    pub general_tag_probs: Vec<f32>,
+    pub embedding: Option<Vec<f32>>,
+}
+
+impl Cache {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(path)))]
+    pub fn open<P: AsRef<Path>>(path: P, model_version: &str) -> Result<Self> {
+        let db = sled::open(path)?;
+        let entries = db.open_tree("entries")?;
+
+        let stored_version = db.get(MODEL_VERSION_KEY)?;
+        if stored_version.as_deref() != Some(model_version.as_bytes()) {
+            entries.clear()?;
+            db.insert(MODEL_VERSION_KEY, model_version.as_bytes())?;
+            db.flush()?;
+        }
+
+        Ok(Self { db, entries })
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        self.entries.clear()?;
+        Ok(())
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub(crate) fn key(img: &ImageRef, augmentation: Option<&Augmentation>) -> [u8; 32] {
+        let (width, height) = img.dimensions();
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&width.to_le_bytes());
+        hasher.update(&height.to_le_bytes());
+        hasher.update(format!("{:?}", img.color()).as_bytes());
+        hasher.update(img.as_bytes());
+        if let Some(augmentation) = augmentation {
+            hasher.update(&[augmentation.flip as u8]);
+            if let Some(ratio) = augmentation.crop_ratio {
+                hasher.update(&ratio.to_le_bytes());
+            }
+        }
+        hasher.finalize().into()
+    }
+
+    pub(crate) fn get(&self, key: &[u8; 32]) -> Result<Option<Entry>> {
+        let value = match self.entries.get(key)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        // [number of general tags (u32)] [general tag probs] [embedding]
+        // corrupted entries are treated as misses and overwritten
+        if value.len() < 4 || value.len() % 4 != 0 {
+            return Ok(None);
+        }
+        let (len, floats) = value.split_at(4);
+        let num_probs = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if num_probs > floats.len() / 4 {
+            return Ok(None);
+        }
+        let mut floats = floats
+            .chunks_exact(4)
+            .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]));
+        let general_tag_probs: Vec<_> = floats.by_ref().take(num_probs).collect();
+        let embedding: Vec<_> = floats.collect();
+
+        Ok(Some(Entry {
+            general_tag_probs,
+            embedding: if embedding.is_empty() {
+                None
+            } else {
+                Some(embedding)
+            },
+        }))
+    }
+
+    pub(crate) fn insert(&self, key: &[u8; 32], entry: &Entry) -> Result<()> {
+        let embedding = entry.embedding.as_deref().unwrap_or_default();
+        let mut value =
+            Vec::with_capacity((1 + entry.general_tag_probs.len() + embedding.len()) * 4);
+        value.extend_from_slice(&(entry.general_tag_probs.len() as u32).to_le_bytes());
+        for x in entry.general_tag_probs.iter().chain(embedding) {
+            value.extend_from_slice(&x.to_le_bytes());
+        }
+
+        self.entries.insert(key, value)?;
+        Ok(())
+    }
+}
+
+/// Hex-encoded digest of a model component, which can be combined into a model version.
+pub fn fingerprint(data: &[u8]) -> String {
+    blake3::hash(data).to_hex().to_string()
+}
+
+/// Model version of cached entries, which only depend on the neural net, the node whose
+/// output is the embedding and the general tags.
+pub fn model_version(
+    neural_net_fingerprint: &str,
+    embedding_node: Option<&str>,
+    general_tags: &[String],
+) -> String {
+    let mut version = format!(
+        "{}-{}",
+        neural_net_fingerprint,
+        fingerprint(general_tags.join("\n").as_bytes())
+    );
+    if let Some(node) = embedding_node {
+        version.push('-');
+        version.push_str(node);
+    }
+    version
+}
@@ -1,24 +1,42 @@
 use crate::{
-    models::{NaiveBayes, NeuralNet},
-    Result,
+    models::{self, Calibration, NaiveBayes, NeuralNet},
+    pixels::ImageRef,
+    saliency, Augmentation, BoundingBox, Error, Occlusion, PixelFormat, Result, SaliencyTarget,
+    Tiling, Timings, View,
 };
 
-use image::DynamicImage;
+#[cfg(feature = "cache")]
+use crate::cache::{self, Cache};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::ModelDir;
+
+use image::{DynamicImage, GenericImageView};
+use instant::Instant;
 use serde::Serialize;
 use std::cmp::Reverse;
-use tract_onnx::tract_core::{ndarray::ArrayView1, tract_data::itertools::Itertools};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use tract_onnx::tract_core::{
+    ndarray::{Array1, Array2, ArrayView1},
+    tract_data::itertools::Itertools,
+};
 
 pub struct Params {
     pub neural_net: NeuralNet,
     pub naive_bayes: NaiveBayes,
+    pub calibration: Option<Calibration>,
     pub general_tags: Vec<String>,
     pub character_tags: Vec<String>,
     pub topk: usize,
+    pub augmentation: Option<Augmentation>,
 }
 
 pub struct Prediction<'a> {
     general_tags: Vec<Tag<'a>>,
     character_tags: Vec<Tag<'a>>,
+    views: Vec<View>,
+    embedding: Option<Vec<f32>>,
+    timings: Option<Timings>,
 }
 
 impl Prediction<'_> {
@@ -29,6 +47,34 @@ impl Prediction<'_> {
     pub fn character(&self) -> &[Tag] {
         &self.character_tags
     }
+
+    pub fn views(&self) -> &[View] {
+        &self.views
+    }
+
+    pub fn embedding(&self) -> Option<&[f32]> {
+        self.embedding.as_deref()
+    }
+
+    /// Available if the classifier was built with [`Classifier::with_timings`].
+    pub fn timings(&self) -> Option<&Timings> {
+        self.timings.as_ref()
+    }
+
+    /// Allows filling in [`Timings::decode`].
+    pub fn timings_mut(&mut self) -> Option<&mut Timings> {
+        self.timings.as_mut()
+    }
+}
+
+pub struct TiledPrediction<'a> {
+    pub tiles: Vec<TilePrediction<'a>>,
+    pub merged: Prediction<'a>,
+}
+
+pub struct TilePrediction<'a> {
+    pub bbox: BoundingBox,
+    pub prediction: Prediction<'a>,
 }
 
 #[derive(Serialize)]
@@ -40,33 +86,365 @@ pub struct Tag<'a> {
 pub struct Classifier {
     neural_net: NeuralNet,
     naive_bayes: NaiveBayes,
+    calibration: Option<Calibration>,
     general_tags: Vec<String>,
     character_tags: Vec<String>,
     topk: usize,
+    augmentation: Option<Augmentation>,
+    timings: bool,
+    #[cfg(feature = "cache")]
+    cache: Option<Cache>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Params {
+    /// Loads the models and the tags from a model directory, see [`ModelDir`].
+    pub fn from_dir<P: Into<PathBuf>>(path: P, topk: usize) -> Result<Self> {
+        let dir = ModelDir::new(path);
+        Ok(Self {
+            neural_net: dir.neural_net()?,
+            naive_bayes: dir.naive_bayes()?,
+            calibration: dir.calibration()?,
+            general_tags: dir.general_tags()?,
+            character_tags: dir.character_tags()?,
+            topk,
+            augmentation: None,
+        })
+    }
 }
 
 impl Classifier {
     pub fn new(params: Params) -> Result<Self> {
+        if let Some(num_characters) = params
+            .calibration
+            .as_ref()
+            .and_then(Calibration::num_characters)
+        {
+            if num_characters != params.character_tags.len() {
+                return Err(Error::CalibrationMismatch);
+            }
+        }
+        if let Some(ratio) = params
+            .augmentation
+            .as_ref()
+            .and_then(|augmentation| augmentation.crop_ratio)
+        {
+            if !Augmentation::is_valid_crop_ratio(ratio) {
+                return Err(Error::InvalidCropRatio(ratio));
+            }
+        }
+
         Ok(Self {
             neural_net: params.neural_net,
             naive_bayes: params.naive_bayes,
+            calibration: params.calibration,
             general_tags: params.general_tags,
             character_tags: params.character_tags,
             topk: params.topk,
+            augmentation: params.augmentation,
+            timings: false,
+            #[cfg(feature = "cache")]
+            cache: None,
         })
     }
 
+    /// Loads a classifier from a model directory, see [`ModelDir`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_dir<P: Into<PathBuf>>(path: P, topk: usize) -> Result<Self> {
+        Self::new(Params::from_dir(path, topk)?)
+    }
+
+    /// Caches neural net outputs so that images seen before are not processed again.
+    #[cfg(feature = "cache")]
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Makes predictions carry [`Timings`] of their stages.
+    pub fn with_timings(mut self) -> Self {
+        self.timings = true;
+        self
+    }
+
     pub fn predict(&self, img: DynamicImage) -> Result<Prediction> {
-        let output = self.neural_net.predict(img)?;
+        self.predict_ref(&img)
+    }
+
+    /// Same as [`Classifier::predict`] but borrows the image.
+    pub fn predict_ref(&self, img: &DynamicImage) -> Result<Prediction> {
+        let scores = self.scores(&ImageRef::Dynamic(img))?;
+        Ok(self.prediction(scores))
+    }
+
+    /// Predicts tags of an image in a raw pixel buffer of `width * height` pixels
+    /// without copying it into a [`DynamicImage`].
+    pub fn predict_rgb(
+        &self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<Prediction> {
+        let img = ImageRef::from_pixels(pixels, width, height, format)?;
+        let scores = self.scores(&img)?;
+        Ok(self.prediction(scores))
+    }
+
+    pub fn predict_tiled(&self, img: DynamicImage, tiling: &Tiling) -> Result<TiledPrediction> {
+        let (width, height) = img.dimensions();
+        let bboxes = tiling.bboxes(width, height);
+
+        let mut tiles = Vec::with_capacity(bboxes.len());
+        for bbox in bboxes {
+            let tile = img.crop_imm(bbox.x, bbox.y, bbox.width, bbox.height);
+            tiles.push((bbox, self.scores(&ImageRef::Dynamic(&tile))?));
+        }
+
+        // merge by taking the maximum score over the whole image and all tiles,
+        // so that a tag present in any part of the image is kept
+        let mut merged = if tiles.len() > 1 {
+            let mut merged = self.scores(&ImageRef::Dynamic(&img))?;
+            // the merged prediction took as long as the whole image and all tiles together
+            for (_, scores) in &tiles {
+                merged.timings.add(&scores.timings);
+            }
+            merged
+        } else {
+            tiles[0].1.clone()
+        };
+        for (_, scores) in &tiles {
+            merged
+                .general_tag_probs
+                .zip_mut_with(&scores.general_tag_probs, |x, &y| *x = x.max(y));
+            merged
+                .character_logits
+                .zip_mut_with(&scores.character_logits, |x, &y| *x = x.max(y));
+        }
+        let merged = self.prediction(merged);
+
+        let tiles = tiles
+            .into_iter()
+            .map(|(bbox, scores)| TilePrediction {
+                bbox,
+                prediction: self.prediction(scores),
+            })
+            .collect();
+
+        Ok(TiledPrediction { tiles, merged })
+    }
+
+    /// Returns probabilities of all general tags, which can be used as a feature vector
+    /// of the image.
+    pub fn general_tag_probs(&self, img: DynamicImage) -> Result<Vec<f32>> {
+        let scores = self.scores(&ImageRef::Dynamic(&img))?;
+        Ok(scores.general_tag_probs.into_raw_vec())
+    }
+
+    /// Computes how much the score of the target drops when each cell of the grid is occluded.
+    pub fn saliency(
+        &self,
+        img: DynamicImage,
+        target: SaliencyTarget,
+        occlusion: &Occlusion,
+    ) -> Result<Array2<f32>> {
+        let (width, height) = img.dimensions();
+        if width == 0 || height == 0 {
+            return Err(Error::EmptyImage);
+        }
+        let shape = occlusion.shape(width, height);
+
+        // resolved before any inference so that an unknown tag fails fast
+        let (index, is_character) = match target {
+            SaliencyTarget::General(name) => (self.general_tag_index(name)?, false),
+            SaliencyTarget::Character(name) => (self.character_tag_index(name)?, true),
+        };
+
+        let score = |img: &DynamicImage| -> Result<f32> {
+            let output = self.neural_net.predict(img)?;
+            let probs: Array1<f32> = output
+                .iter()
+                .take(self.general_tags.len())
+                .copied()
+                .collect();
+            Ok(if is_character {
+                sigmoid(self.character_logits(probs.view())[index])
+            } else {
+                probs[index]
+            })
+        };
+
+        let baseline = score(&img)?;
+        let mut drops = Vec::with_capacity(shape.0 * shape.1);
+        for bbox in occlusion.bboxes(width, height) {
+            drops.push(baseline - score(&saliency::occlude(&img, &bbox))?);
+        }
+
+        Ok(Array2::from_shape_vec(shape, drops)?)
+    }
+
+    pub fn general_tags(&self) -> &[String] {
+        &self.general_tags
+    }
+
+    pub fn character_tags(&self) -> &[String] {
+        &self.character_tags
+    }
+
+    fn general_tag_index(&self, name: &str) -> Result<usize> {
+        self.general_tags
+            .iter()
+            .position(|tag| tag == name)
+            .ok_or_else(|| Error::UnknownTag(name.to_owned()))
+    }
+
+    fn character_tag_index(&self, name: &str) -> Result<usize> {
+        self.character_tags
+            .iter()
+            .position(|tag| tag == name)
+            .ok_or_else(|| Error::UnknownTag(name.to_owned()))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "predict",
+            skip(self, img),
+            fields(width = img.dimensions().0, height = img.dimensions().1)
+        )
+    )]
+    pub(crate) fn scores(&self, img: &ImageRef) -> Result<Scores> {
+        let (width, height) = img.dimensions();
+        // checked before augmented views are cropped from the image
+        if width == 0 || height == 0 {
+            return Err(Error::EmptyImage);
+        }
+        #[allow(unused_mut)]
+        let mut timings = Timings {
+            width,
+            height,
+            padding: models::padding(width, height),
+            ..Default::default()
+        };
+
+        #[cfg(feature = "cache")]
+        {
+            if let Some(cache) = &self.cache {
+                let key = Cache::key(img, self.augmentation.as_ref());
+                // a failing cache must not fail predictions, so errors are treated as misses
+                let entry = cache.get(&key).unwrap_or_else(|_err| {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %_err, "Failed to read from cache");
+                    None
+                });
+                if let Some(entry) = entry {
+                    if entry.general_tag_probs.len() == self.general_tags.len() {
+                        let views = match &self.augmentation {
+                            Some(augmentation) => augmentation.view_list(),
+                            None => vec![View::Original],
+                        };
+                        let general_tag_probs = Array1::from(entry.general_tag_probs);
+                        let start = Instant::now();
+                        let character_logits = self.character_logits(general_tag_probs.view());
+                        timings.head = start.elapsed();
+                        timings.cache_hit = true;
+                        return Ok(Scores {
+                            views,
+                            general_tag_probs,
+                            character_logits,
+                            embedding: entry.embedding.map(Array1::from),
+                            timings,
+                        });
+                    }
+                }
+
+                let scores = self.compute_scores(img, timings)?;
+                let entry = cache::Entry {
+                    general_tag_probs: scores.general_tag_probs.to_vec(),
+                    embedding: scores.embedding.as_ref().map(Array1::to_vec),
+                };
+                if let Err(_err) = cache.insert(&key, &entry) {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %_err, "Failed to write to cache");
+                }
+                return Ok(scores);
+            }
+        }
+
+        self.compute_scores(img, timings)
+    }
+
+    fn compute_scores(&self, img: &ImageRef, mut timings: Timings) -> Result<Scores> {
+        let augmented = match &self.augmentation {
+            Some(augmentation) => augmentation.views(img),
+            None => Vec::new(),
+        };
+        let views: Vec<_> = std::iter::once(View::Original)
+            .chain(augmented.iter().map(|(view, _)| *view))
+            .collect();
+        let augmented: Vec<_> = augmented
+            .iter()
+            .map(|(_, img)| ImageRef::Dynamic(img))
+            .collect();
+        let imgs = std::iter::once(img).chain(&augmented);
+
+        // average general tag probabilities and embeddings over all views
+        let num_general_tags = self.general_tags.len();
+        let mut general_tag_probs = Array1::<f32>::zeros(num_general_tags);
+        let mut embedding: Option<Array1<f32>> = None;
+        for img in imgs {
+            let (output, output_embedding) = self.neural_net.predict_image(img, &mut timings)?;
+            for (prob, output) in general_tag_probs.iter_mut().zip(output.iter()) {
+                *prob += output;
+            }
+
+            if let Some(output_embedding) = output_embedding {
+                match &mut embedding {
+                    Some(embedding) => {
+                        for (x, y) in embedding.iter_mut().zip(output_embedding.iter()) {
+                            *x += y;
+                        }
+                    }
+                    None => embedding = Some(output_embedding.iter().copied().collect()),
+                }
+            }
+        }
+        general_tag_probs /= views.len() as f32;
+        if let Some(embedding) = &mut embedding {
+            *embedding /= views.len() as f32;
+        }
 
-        let general_tag_probs = &output.as_slice::<f32>()?[..self.general_tags.len()];
-        let general_tag_probs =
-            ArrayView1::from_shape((self.general_tags.len(),), general_tag_probs)?;
+        let start = Instant::now();
+        let character_logits = self.character_logits(general_tag_probs.view());
+        timings.head = start.elapsed();
 
+        Ok(Scores {
+            views,
+            general_tag_probs,
+            character_logits,
+            embedding,
+            timings,
+        })
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "head", skip(self, general_tag_probs))
+    )]
+    fn character_logits(&self, general_tag_probs: ArrayView1<f32>) -> Array1<f32> {
+        let character_logits = self.naive_bayes.predict(general_tag_probs);
+        if let Some(calibration) = &self.calibration {
+            calibration.apply(character_logits)
+        } else {
+            character_logits
+        }
+    }
+
+    pub(crate) fn prediction(&self, scores: Scores) -> Prediction {
         let general_tags = self
             .general_tags
             .iter()
-            .zip(general_tag_probs.iter())
+            .zip(scores.general_tag_probs.iter())
             .map(|(name, prob)| {
                 Reverse(ScoreCmp(Tag {
                     name: name.as_str(),
@@ -77,11 +455,10 @@ impl Classifier {
             .map(|Reverse(ScoreCmp(x))| x)
             .collect();
 
-        let character_logits = self.naive_bayes.predict(general_tag_probs);
         let character_tags = self
             .character_tags
             .iter()
-            .zip(character_logits.iter())
+            .zip(scores.character_logits.iter())
             .map(|(name, logit)| {
                 Reverse(ScoreCmp(Tag {
                     name: name.as_str(),
@@ -95,13 +472,37 @@ impl Classifier {
             })
             .collect();
 
-        Ok(Prediction {
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            top_general = ?general_tags.first().map(|tag: &Tag| tag.name),
+            top_character = ?character_tags.first().map(|tag: &Tag| tag.name),
+            "Predicted tags"
+        );
+
+        Prediction {
             general_tags,
             character_tags,
-        })
+            views: scores.views,
+            embedding: scores.embedding.map(Array1::into_raw_vec),
+            timings: if self.timings {
+                Some(scores.timings)
+            } else {
+                None
+            },
+        }
     }
 }
 
+// scores of an image before picking top-k tags
+#[derive(Clone)]
+pub(crate) struct Scores {
+    views: Vec<View>,
+    general_tag_probs: Array1<f32>,
+    character_logits: Array1<f32>,
+    embedding: Option<Array1<f32>>,
+    timings: Timings,
+}
+
 fn sigmoid(x: f32) -> f32 {
     1.0 / (1.0 + f32::exp(-x))
 }
@@ -135,3 +536,63 @@ impl Ord for ScoreCmp<'_> {
         left.cmp(&right)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::InferenceBackend;
+    use ndarray_npy::NpzWriter;
+    use std::{io::Cursor, thread, time::Duration};
+    use tract_onnx::tract_core::ndarray::{arr1, arr2, ArrayD, ArrayView4, IxDyn};
+
+    struct ConstantBackend;
+
+    impl InferenceBackend for ConstantBackend {
+        fn run(&self, _input: ArrayView4<f32>) -> Result<Vec<ArrayD<f32>>> {
+            thread::sleep(Duration::from_millis(1));
+            Ok(vec![ArrayD::from_elem(IxDyn(&[1, 2]), 0.5)])
+        }
+    }
+
+    fn classifier() -> Classifier {
+        let mut npz = NpzWriter::new(Cursor::new(Vec::new()));
+        npz.add_array("a.npy", &arr2(&[[1f32], [-1.]])).unwrap();
+        npz.add_array("b.npy", &arr1(&[0f32])).unwrap();
+        let mut reader = npz.finish().unwrap();
+        reader.set_position(0);
+
+        let params = Params {
+            neural_net: NeuralNet::with_backend(ConstantBackend),
+            naive_bayes: NaiveBayes::new(reader).unwrap(),
+            calibration: None,
+            general_tags: vec!["a".to_owned(), "b".to_owned()],
+            character_tags: vec!["c".to_owned()],
+            topk: 2,
+            augmentation: None,
+        };
+        Classifier::new(params).unwrap().with_timings()
+    }
+
+    #[test]
+    fn merged_timings_include_tiles() {
+        let classifier = classifier();
+        let tiling = Tiling {
+            tile_size: 16,
+            overlap: 0.,
+        };
+        let prediction = classifier
+            .predict_tiled(DynamicImage::new_rgb8(32, 16), &tiling)
+            .unwrap();
+        assert_eq!(prediction.tiles.len(), 2);
+
+        let merged = prediction.merged.timings().unwrap();
+        let tiles = prediction
+            .tiles
+            .iter()
+            .map(|tile| tile.prediction.timings().unwrap());
+        let network: Duration = tiles.clone().map(|timings| timings.network).sum();
+        let total: Duration = tiles.map(Timings::total).sum();
+        assert!(merged.network >= network);
+        assert!(merged.total() >= total);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{Error, Result};
+
+use serde::Serialize;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+const MAGIC: &[u8; 4] = b"WBIX";
+const VERSION: u32 = 1;
+
+/// Brute-force nearest neighbour index of vectors under cosine distance.
+pub struct SimilarityIndex {
+    dim: usize,
+    keys: Vec<String>,
+    positions: HashMap<String, usize>,
+    vectors: Vec<f32>,
+}
+
+#[derive(Serialize)]
+pub struct Neighbor<'a> {
+    pub key: &'a str,
+    pub distance: f32,
+}
+
+impl SimilarityIndex {
+    pub fn new(dim: usize) -> Self {
+        Self {
+            dim,
+            keys: Vec::new(),
+            positions: HashMap::new(),
+            vectors: Vec::new(),
+        }
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u32(&mut reader)? != VERSION {
+            return Err(Error::InvalidIndex);
+        }
+
+        let dim = read_u32(&mut reader)? as usize;
+        let len = read_u32(&mut reader)? as usize;
+
+        // sizes come from the stream, so nothing is allocated before the bytes are read
+        let mut keys = Vec::new();
+        for _ in 0..len {
+            let key_len = read_u32(&mut reader)? as usize;
+            let key = read_bytes(&mut reader, key_len)?;
+            keys.push(String::from_utf8(key).map_err(|_| Error::InvalidIndex)?);
+        }
+
+        let size = len
+            .checked_mul(dim)
+            .and_then(|n| n.checked_mul(4))
+            .ok_or(Error::InvalidIndex)?;
+        let vectors = read_bytes(&mut reader, size)?
+            .chunks_exact(4)
+            .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+            .collect();
+
+        let positions = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (key.clone(), i))
+            .collect();
+
+        Ok(Self {
+            dim,
+            keys,
+            positions,
+            vectors,
+        })
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.dim as u32).to_le_bytes())?;
+        writer.write_all(&(self.keys.len() as u32).to_le_bytes())?;
+        for key in &self.keys {
+            writer.write_all(&(key.len() as u32).to_le_bytes())?;
+            writer.write_all(key.as_bytes())?;
+        }
+        for x in &self.vectors {
+            writer.write_all(&x.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Adds a vector, replacing the one previously added with the same key.
+    pub fn insert(&mut self, key: String, vector: &[f32]) -> Result<()> {
+        if vector.len() != self.dim {
+            return Err(Error::DimensionMismatch);
+        }
+
+        let normalized = normalize(vector);
+        match self.positions.get(&key) {
+            Some(&i) => {
+                self.vectors[i * self.dim..][..self.dim].copy_from_slice(&normalized);
+            }
+            None => {
+                self.positions.insert(key.clone(), self.keys.len());
+                self.keys.push(key);
+                self.vectors.extend_from_slice(&normalized);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the `k` nearest vectors to `vector`, closest first.
+    pub fn search(&self, vector: &[f32], k: usize) -> Result<Vec<Neighbor>> {
+        if vector.len() != self.dim {
+            return Err(Error::DimensionMismatch);
+        }
+
+        let query = normalize(vector);
+        let mut neighbors: Vec<_> = self
+            .keys
+            .iter()
+            .zip(self.vectors.chunks_exact(self.dim.max(1)))
+            .map(|(key, x)| {
+                let similarity: f32 = query.iter().zip(x).map(|(a, b)| a * b).sum();
+                Neighbor {
+                    key,
+                    distance: 1. - similarity,
+                }
+            })
+            .collect();
+
+        // only the `k` nearest vectors are sorted
+        let by_distance = |a: &Neighbor, b: &Neighbor| {
+            a.distance
+                .partial_cmp(&b.distance)
+                .unwrap_or(Ordering::Equal)
+        };
+        if k < neighbors.len() {
+            neighbors.select_nth_unstable_by(k, by_distance);
+            neighbors.truncate(k);
+        }
+        neighbors.sort_unstable_by(by_distance);
+        Ok(neighbors)
+    }
+}
+
+fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0. {
+        vector.iter().map(|x| x / norm).collect()
+    } else {
+        vector.to_vec()
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let buf = read_bytes(reader, 4)?;
+    Ok(u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]))
+}
+
+/// Reads exactly `len` bytes, growing the buffer only as bytes arrive so that a corrupt
+/// length cannot exhaust memory.
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(Error::InvalidIndex);
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_read_search() {
+        let mut index = SimilarityIndex::new(3);
+        index.insert("x".to_owned(), &[1., 0., 0.]).unwrap();
+        index.insert("y".to_owned(), &[0., 2., 0.]).unwrap();
+        index.insert("xy".to_owned(), &[1., 1., 0.]).unwrap();
+        // replaces the previous vector
+        index.insert("y".to_owned(), &[0., 1., 1.]).unwrap();
+
+        let mut buf = Vec::new();
+        index.write(&mut buf).unwrap();
+        let index = SimilarityIndex::read(&buf[..]).unwrap();
+        assert_eq!(index.dim(), 3);
+        assert_eq!(index.len(), 3);
+
+        let neighbors = index.search(&[3., 0., 0.], 2).unwrap();
+        let keys: Vec<_> = neighbors.iter().map(|neighbor| neighbor.key).collect();
+        assert_eq!(keys, vec!["x", "xy"]);
+        assert!(neighbors[0].distance.abs() < 1e-6);
+        assert!((neighbors[1].distance - (1. - 0.5f32.sqrt())).abs() < 1e-6);
+
+        assert_eq!(index.search(&[0., 1., 1.], 1).unwrap()[0].key, "y");
+        assert!(matches!(
+            index.search(&[1., 0.], 1),
+            Err(Error::DimensionMismatch)
+        ));
+    }
+
+    #[test]
+    fn read_rejects_invalid_header() {
+        let mut buf = Vec::new();
+        SimilarityIndex::new(2).write(&mut buf).unwrap();
+        buf[0] = b'X';
+        assert!(matches!(
+            SimilarityIndex::read(&buf[..]),
+            Err(Error::InvalidIndex)
+        ));
+    }
+
+    #[test]
+    fn read_rejects_corrupt_sizes() {
+        let mut index = SimilarityIndex::new(2);
+        index.insert("x".to_owned(), &[1., 0.]).unwrap();
+        let mut buf = Vec::new();
+        index.write(&mut buf).unwrap();
+
+        // truncated vectors
+        assert!(matches!(
+            SimilarityIndex::read(&buf[..buf.len() - 1]),
+            Err(Error::InvalidIndex)
+        ));
+
+        // huge dimension and number of vectors
+        let mut huge = buf.clone();
+        huge[8..16].copy_from_slice(&[0xff; 8]);
+        assert!(matches!(
+            SimilarityIndex::read(&huge[..]),
+            Err(Error::InvalidIndex)
+        ));
+
+        // huge key length
+        let mut huge = buf;
+        huge[16..20].copy_from_slice(&[0xff; 4]);
+        assert!(matches!(
+            SimilarityIndex::read(&huge[..]),
+            Err(Error::InvalidIndex)
+        ));
+    }
+}
@@ -1,8 +1,35 @@
+#[cfg(feature = "async")]
+mod async_classifier;
+mod augmentation;
+pub mod backend;
+#[cfg(feature = "cache")]
+pub mod cache;
 mod classifier;
+mod index;
+pub mod model_dir;
 pub mod models;
+mod pixels;
+#[cfg(not(target_arch = "wasm32"))]
+mod pool;
+mod saliency;
+mod tiling;
+mod timings;
 
-pub use classifier::{Classifier, Params, Prediction, Tag};
+#[cfg(feature = "async")]
+pub use async_classifier::AsyncClassifier;
+pub use augmentation::{Augmentation, View};
+pub use classifier::{Classifier, Params, Prediction, Tag, TilePrediction, TiledPrediction};
 pub use image;
+pub use index::{Neighbor, SimilarityIndex};
+#[cfg(not(target_arch = "wasm32"))]
+pub use model_dir::ModelDir;
+pub use pixels::PixelFormat;
+#[cfg(not(target_arch = "wasm32"))]
+pub use pool::{TaggingPool, TaggingResults};
+pub use saliency::{Occlusion, SaliencyTarget};
+pub use tiling::{BoundingBox, Tiling};
+pub use timings::Timings;
+pub use tract_onnx::tract_core::ndarray;
 
 use thiserror::Error;
 
@@ -16,6 +43,53 @@ pub enum Error {
 
     #[error(transparent)]
     ReadNpz(#[from] ndarray_npy::ReadNpzError),
+
+    #[error("Calibration parameters do not match the number of characters")]
+    CalibrationMismatch,
+
+    #[error("Temperature must be positive and finite, got {0}")]
+    InvalidTemperature(f32),
+
+    #[error("Crop ratio must be greater than 0 and at most 1, got {0}")]
+    InvalidCropRatio(f32),
+
+    #[error("Invalid quantized naive Bayes model")]
+    InvalidNaiveBayes,
+
+    #[error("Neural net returned no outputs")]
+    MissingOutput,
+
+    #[error("Image is empty")]
+    EmptyImage,
+
+    #[error("Pixel buffer size does not match the dimensions and the format")]
+    PixelBufferSize,
+
+    #[error("Unknown tag: {0}")]
+    UnknownTag(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+
+    #[error("Worker thread panicked")]
+    WorkerPanicked,
+
+    #[error("Invalid similarity index")]
+    InvalidIndex,
+
+    #[error("Vector dimension does not match the index")]
+    DimensionMismatch,
+
+    #[cfg(feature = "cache")]
+    #[error(transparent)]
+    Cache(#[from] sled::Error),
+
+    #[cfg(feature = "onnxruntime")]
+    #[error(transparent)]
+    OnnxRuntime(#[from] onnxruntime::OrtError),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,130 @@
+//! Files of a model directory, which are loaded in the same way by every front-end.
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    models::{Calibration, NaiveBayes, NeuralNet},
+    Result,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+pub const NEURAL_NET: &str = "neural-net.onnx";
+pub const COMPILED_NEURAL_NET: &str = "neural-net.nnef.tar";
+pub const NAIVE_BAYES: &str = "naive-bayes.npz";
+pub const QUANTIZED_NAIVE_BAYES: &str = "naive-bayes.q8.bin";
+pub const CALIBRATION: &str = "calibration.npz";
+pub const GENERAL_TAGS: &str = "general-tags.txt";
+pub const CHARACTER_TAGS: &str = "character-tags.txt";
+
+/// Directory containing the model files.
+///
+/// The compiled neural net and the quantized naive Bayes are preferred to the original
+/// models unless they are older, so that a stale one does not shadow an updated model.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct ModelDir {
+    path: PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ModelDir {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+
+    /// Path of the neural net to load, either compiled or ONNX.
+    pub fn neural_net_path(&self) -> PathBuf {
+        self.preferred(COMPILED_NEURAL_NET, NEURAL_NET)
+    }
+
+    /// Path of the naive Bayes to load, either quantized or not.
+    pub fn naive_bayes_path(&self) -> PathBuf {
+        self.preferred(QUANTIZED_NAIVE_BAYES, NAIVE_BAYES)
+    }
+
+    /// Compiled or quantized models which exist but are ignored since they are older
+    /// than the models they were created from.
+    pub fn stale_files(&self) -> Vec<PathBuf> {
+        [
+            (COMPILED_NEURAL_NET, NEURAL_NET),
+            (QUANTIZED_NAIVE_BAYES, NAIVE_BAYES),
+        ]
+        .iter()
+        .map(|(derived, source)| (self.join(derived), self.join(source)))
+        .filter(|(derived, source)| derived.exists() && !is_up_to_date(derived, source))
+        .map(|(derived, _)| derived)
+        .collect()
+    }
+
+    pub fn neural_net(&self) -> Result<NeuralNet> {
+        NeuralNet::from_bytes(&fs::read(self.neural_net_path())?)
+    }
+
+    /// Loads the naive Bayes, memory-mapping it if it is quantized.
+    pub fn naive_bayes(&self) -> Result<NaiveBayes> {
+        let quantized_path = self.join(QUANTIZED_NAIVE_BAYES);
+        if self.naive_bayes_path() == quantized_path {
+            NaiveBayes::open_quantized(quantized_path)
+        } else {
+            NaiveBayes::new(BufReader::new(File::open(self.join(NAIVE_BAYES))?))
+        }
+    }
+
+    /// Loads the calibration if the directory has one.
+    pub fn calibration(&self) -> Result<Option<Calibration>> {
+        let path = self.join(CALIBRATION);
+        if path.exists() {
+            Ok(Some(Calibration::new(BufReader::new(File::open(path)?))?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn general_tags(&self) -> Result<Vec<String>> {
+        read_list(self.join(GENERAL_TAGS))
+    }
+
+    pub fn character_tags(&self) -> Result<Vec<String>> {
+        read_list(self.join(CHARACTER_TAGS))
+    }
+
+    fn preferred(&self, derived: &str, source: &str) -> PathBuf {
+        let (derived, source) = (self.join(derived), self.join(source));
+        if is_up_to_date(&derived, &source) {
+            derived
+        } else {
+            source
+        }
+    }
+}
+
+/// Whether `derived` exists and is not older than `source`, which it was created from.
+#[cfg(not(target_arch = "wasm32"))]
+fn is_up_to_date(derived: &Path, source: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(derived), modified(source)) {
+        (Err(_), _) => false,
+        (Ok(derived_time), Ok(source_time)) => derived_time >= source_time,
+        // the source may have been removed after the derived file was created
+        (Ok(_), Err(_)) => true,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_list<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+    Ok(BufReader::new(File::open(path)?)
+        .lines()
+        .collect::<std::io::Result<_>>()?)
+}
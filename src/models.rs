@@ -1,5 +1,10 @@
+mod calibration;
 mod naive_bayes;
 mod neural_net;
+mod preprocess;
 
+pub use calibration::Calibration;
 pub use naive_bayes::NaiveBayes;
 pub use neural_net::NeuralNet;
+pub(crate) use preprocess::padding;
+pub use preprocess::Preprocessor;
@@ -0,0 +1,76 @@
+use crate::{Error, Result};
+
+use ndarray_npy::NpzReader;
+use std::io::{Read, Seek};
+use tract_onnx::tract_core::ndarray::{Array0, Array1};
+
+pub enum Calibration {
+    Temperature(f32),
+    Platt { a: Array1<f32>, b: Array1<f32> },
+}
+
+impl Calibration {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "load_calibration", skip(reader))
+    )]
+    pub fn new<R: Read + Seek>(reader: R) -> Result<Self> {
+        let mut npz = NpzReader::new(reader)?;
+        if npz.names()?.iter().any(|name| name == "temperature.npy") {
+            let temperature: Array0<f32> = npz.by_name("temperature.npy")?;
+            let temperature = temperature[()];
+            // dividing by anything else turns every score into inf or NaN
+            if !(temperature.is_finite() && temperature > 0.) {
+                return Err(Error::InvalidTemperature(temperature));
+            }
+            return Ok(Self::Temperature(temperature));
+        }
+
+        let a: Array1<f32> = npz.by_name("a.npy")?;
+        let b: Array1<f32> = npz.by_name("b.npy")?;
+        if a.len() != b.len() {
+            return Err(Error::CalibrationMismatch);
+        }
+
+        Ok(Self::Platt { a, b })
+    }
+
+    pub fn num_characters(&self) -> Option<usize> {
+        match self {
+            Self::Temperature(_) => None,
+            Self::Platt { a, .. } => Some(a.len()),
+        }
+    }
+
+    pub fn apply(&self, logits: Array1<f32>) -> Array1<f32> {
+        match self {
+            Self::Temperature(temperature) => logits / *temperature,
+            Self::Platt { a, b } => logits * a + b,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray_npy::NpzWriter;
+    use std::io::Cursor;
+    use tract_onnx::tract_core::ndarray::arr0;
+
+    fn temperature(temperature: f32) -> Result<Calibration> {
+        let mut npz = NpzWriter::new(Cursor::new(Vec::new()));
+        npz.add_array("temperature.npy", &arr0(temperature))
+            .unwrap();
+        let mut reader = npz.finish().unwrap();
+        reader.set_position(0);
+        Calibration::new(reader)
+    }
+
+    #[test]
+    fn rejects_invalid_temperature() {
+        assert!(matches!(temperature(1.5), Ok(Calibration::Temperature(t)) if t == 1.5));
+        for &t in &[0., -1., f32::NAN, f32::INFINITY] {
+            assert!(matches!(temperature(t), Err(Error::InvalidTemperature(_))));
+        }
+    }
+}
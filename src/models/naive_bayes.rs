@@ -1,24 +1,434 @@
-use crate::Result;
+use crate::{Error, Result};
 
+#[cfg(not(target_arch = "wasm32"))]
+use memmap2::Mmap;
 use ndarray_npy::NpzReader;
-use std::io::{Read, Seek};
-use tract_onnx::tract_core::ndarray::{Array1, Array2, ArrayView1};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::File, path::Path};
+use std::{
+    io::{Cursor, Read, Seek, Write},
+    ops::Deref,
+};
+use tract_onnx::tract_core::ndarray::{Array1, Array2, ArrayView1, Axis};
+
+const QUANTIZED_MAGIC: &[u8; 4] = b"WBNB";
+const QUANTIZED_VERSION: u32 = 1;
+const QUANTIZED_HEADER_LEN: usize = 16;
 
 pub struct NaiveBayes {
-    array_a: Array2<f32>,
+    array_a: Weights,
     array_b: Array1<f32>,
+    sparse: Option<Sparse>,
+}
+
+struct Sparse {
+    cutoff: f32,
+    // probability assumed for tags below the cutoff
+    fill: f32,
+    // logits when every probability is `fill`
+    baseline: Array1<f32>,
+}
+
+enum Weights {
+    Dense(Array2<f32>),
+
+    // int8 matrix with per-column scale, stored in row-major order
+    Quantized {
+        data: Bytes,
+        offset: usize,
+        num_rows: usize,
+        num_cols: usize,
+        scale: Array1<f32>,
+    },
+}
+
+enum Bytes {
+    Owned(Vec<u8>),
+    #[cfg(not(target_arch = "wasm32"))]
+    Mapped(Mmap),
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Owned(bytes) => bytes,
+            #[cfg(not(target_arch = "wasm32"))]
+            Self::Mapped(mmap) => mmap,
+        }
+    }
 }
 
 impl NaiveBayes {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "load_naive_bayes", skip(reader))
+    )]
     pub fn new<R: Read + Seek>(reader: R) -> Result<Self> {
         let mut npz = NpzReader::new(reader)?;
         Ok(Self {
-            array_a: npz.by_name("a.npy")?,
+            array_a: Weights::Dense(npz.by_name("a.npy")?),
             array_b: npz.by_name("b.npy")?,
+            sparse: None,
         })
     }
 
+    /// Makes [`NaiveBayes::predict`] accumulate only rows of general tags with
+    /// probabilities above `cutoff`.
+    ///
+    /// This is an approximation: probabilities of the other tags are assumed to be half the
+    /// cutoff, whose contribution is precomputed here. Lower cutoffs give results closer to
+    /// the exact ones.
+    pub fn with_sparse_cutoff(mut self, cutoff: f32) -> Self {
+        let fill = cutoff / 2.;
+        let baseline = self.array_a.column_sums() * fill + &self.array_b;
+        self.sparse = Some(Sparse {
+            cutoff,
+            fill,
+            baseline,
+        });
+        self
+    }
+
+    /// Loads either a `.npz` model or a quantized one, telling them apart by their contents.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        if bytes.starts_with(QUANTIZED_MAGIC) {
+            Self::from_quantized(bytes)
+        } else {
+            Self::new(Cursor::new(bytes))
+        }
+    }
+
+    /// Loads a model written by [`NaiveBayes::write_quantized`].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "load_naive_bayes",
+            skip(bytes),
+            fields(len = bytes.len())
+        )
+    )]
+    pub fn from_quantized(bytes: Vec<u8>) -> Result<Self> {
+        Self::parse_quantized(Bytes::Owned(bytes))
+    }
+
+    /// Memory-maps a model written by [`NaiveBayes::write_quantized`],
+    /// so that the matrix is not read into memory as a whole.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "load_naive_bayes",
+            skip(path),
+            fields(path = %path.as_ref().display())
+        )
+    )]
+    pub fn open_quantized<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+
+        // SAFETY: the model file is assumed not to be modified while it is mapped
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        Self::parse_quantized(Bytes::Mapped(mmap))
+    }
+
+    /// Writes the model with the matrix quantized to int8 with per-column scale.
+    ///
+    /// The format is uncompressed so that it can be memory-mapped.
+    pub fn write_quantized<W: Write>(&self, mut writer: W) -> Result<()> {
+        let (num_rows, num_cols) = self.shape();
+        writer.write_all(QUANTIZED_MAGIC)?;
+        writer.write_all(&QUANTIZED_VERSION.to_le_bytes())?;
+        writer.write_all(&(num_rows as u32).to_le_bytes())?;
+        writer.write_all(&(num_cols as u32).to_le_bytes())?;
+        for x in &self.array_b {
+            writer.write_all(&x.to_le_bytes())?;
+        }
+
+        match &self.array_a {
+            Weights::Dense(a) => {
+                let scale = a.fold_axis(Axis(0), 0f32, |m, x| m.max(x.abs())) / 127.;
+                for x in &scale {
+                    writer.write_all(&x.to_le_bytes())?;
+                }
+
+                let mut row_buf = vec![0; num_cols];
+                for row in a.outer_iter() {
+                    for ((q, x), s) in row_buf.iter_mut().zip(row).zip(&scale) {
+                        let q_i8 = if *s > 0. { (x / s).round() as i8 } else { 0 };
+                        *q = q_i8 as u8;
+                    }
+                    writer.write_all(&row_buf)?;
+                }
+            }
+            Weights::Quantized {
+                data,
+                offset,
+                scale,
+                ..
+            } => {
+                for x in scale {
+                    writer.write_all(&x.to_le_bytes())?;
+                }
+                writer.write_all(&data[*offset..][..num_rows * num_cols])?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn predict(&self, probs: ArrayView1<f32>) -> Array1<f32> {
-        probs.dot(&self.array_a) + &self.array_b
+        if let Some(sparse) = &self.sparse {
+            // replace the fill of each active tag with its actual probability
+            let mut logits = sparse.baseline.clone();
+            for (i, p) in probs.iter().enumerate() {
+                if *p > sparse.cutoff {
+                    self.array_a.add_row(i, p - sparse.fill, &mut logits);
+                }
+            }
+            return logits;
+        }
+
+        match &self.array_a {
+            Weights::Dense(a) => probs.dot(a) + &self.array_b,
+            Weights::Quantized {
+                data,
+                offset,
+                num_rows,
+                num_cols,
+                scale,
+            } => {
+                let matrix = &data[*offset..][..num_rows * num_cols];
+                let mut logits = Array1::<f32>::zeros(*num_cols);
+                for (p, row) in probs.iter().zip(matrix.chunks_exact(*num_cols)) {
+                    for (logit, q) in logits.iter_mut().zip(row) {
+                        *logit += p * (*q as i8) as f32;
+                    }
+                }
+                logits * scale + &self.array_b
+            }
+        }
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        match &self.array_a {
+            Weights::Dense(a) => a.dim(),
+            Weights::Quantized {
+                num_rows, num_cols, ..
+            } => (*num_rows, *num_cols),
+        }
+    }
+
+    fn parse_quantized(data: Bytes) -> Result<Self> {
+        if data.len() < QUANTIZED_HEADER_LEN || &data[..4] != QUANTIZED_MAGIC {
+            return Err(Error::InvalidNaiveBayes);
+        }
+        let read_u32 = |i: usize| {
+            u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]) as usize
+        };
+        if read_u32(4) != QUANTIZED_VERSION as usize {
+            return Err(Error::InvalidNaiveBayes);
+        }
+
+        let (num_rows, num_cols) = (read_u32(8), read_u32(12));
+        // the dimensions are untrusted and may overflow on 32-bit targets
+        let offset = num_cols
+            .checked_mul(2 * 4)
+            .and_then(|len| len.checked_add(QUANTIZED_HEADER_LEN))
+            .ok_or(Error::InvalidNaiveBayes)?;
+        let len = num_rows
+            .checked_mul(num_cols)
+            .and_then(|len| len.checked_add(offset));
+        if len != Some(data.len()) {
+            return Err(Error::InvalidNaiveBayes);
+        }
+
+        let read_f32s = |start: usize| -> Array1<f32> {
+            data[start..][..num_cols * 4]
+                .chunks_exact(4)
+                .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+                .collect()
+        };
+        let array_b = read_f32s(QUANTIZED_HEADER_LEN);
+        let scale = read_f32s(QUANTIZED_HEADER_LEN + num_cols * 4);
+
+        Ok(Self {
+            array_a: Weights::Quantized {
+                data,
+                offset,
+                num_rows,
+                num_cols,
+                scale,
+            },
+            array_b,
+            sparse: None,
+        })
+    }
+}
+
+impl Weights {
+    fn column_sums(&self) -> Array1<f32> {
+        match self {
+            Self::Dense(a) => a.sum_axis(Axis(0)),
+            Self::Quantized {
+                data,
+                offset,
+                num_rows,
+                num_cols,
+                scale,
+            } => {
+                let matrix = &data[*offset..][..num_rows * num_cols];
+                let mut sums = Array1::<f32>::zeros(*num_cols);
+                for row in matrix.chunks_exact(*num_cols) {
+                    for (sum, q) in sums.iter_mut().zip(row) {
+                        *sum += (*q as i8) as f32;
+                    }
+                }
+                sums * scale
+            }
+        }
+    }
+
+    fn add_row(&self, i: usize, weight: f32, logits: &mut Array1<f32>) {
+        match self {
+            Self::Dense(a) => logits.scaled_add(weight, &a.row(i)),
+            Self::Quantized {
+                data,
+                offset,
+                num_cols,
+                scale,
+                ..
+            } => {
+                let row = &data[*offset + i * num_cols..][..*num_cols];
+                for ((logit, q), s) in logits.iter_mut().zip(row).zip(scale) {
+                    *logit += weight * (*q as i8) as f32 * s;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tract_onnx::tract_core::ndarray::{arr1, arr2};
+
+    fn dense() -> NaiveBayes {
+        NaiveBayes {
+            array_a: Weights::Dense(arr2(&[
+                [1.5, -0.25, 0.],
+                [-3., 0.5, 0.],
+                [0.75, 2., 0.],
+                [0.1, -1., 0.],
+            ])),
+            array_b: arr1(&[-1., 0.5, 2.]),
+            sparse: None,
+        }
+    }
+
+    fn quantized_bytes() -> Vec<u8> {
+        let mut buf = Vec::new();
+        dense().write_quantized(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn quantized_round_trip() {
+        let buf = quantized_bytes();
+        let quantized = NaiveBayes::from_quantized(buf.clone()).unwrap();
+        assert_eq!(quantized.shape(), (4, 3));
+        assert_eq!(quantized.array_b, arr1(&[-1f32, 0.5, 2.]));
+
+        // writing an already quantized model doesn't change it
+        let mut rewritten = Vec::new();
+        quantized.write_quantized(&mut rewritten).unwrap();
+        assert_eq!(rewritten, buf);
+    }
+
+    #[test]
+    fn quantized_logits_are_close_to_dense() {
+        let dense = dense();
+        let quantized = NaiveBayes::from_quantized(quantized_bytes()).unwrap();
+        let probs = arr1(&[0.9, 0.1, 0.6, 1.]);
+
+        // each weight is off by at most half of its column's scale, i.e. max |a| / 254
+        let max_abs = [3., 2., 0.];
+        let expected = dense.predict(probs.view());
+        let actual = quantized.predict(probs.view());
+        for ((e, a), m) in expected.iter().zip(&actual).zip(&max_abs) {
+            let bound = probs.sum() * m / 254. + 1e-6;
+            assert!((e - a).abs() <= bound, "{} vs {}", e, a);
+        }
+    }
+
+    #[test]
+    fn sparse_fills_tags_below_cutoff() {
+        let probs = arr1(&[0.9, 0.05, 0.6, 0.]);
+        let filled = arr1(&[0.9, 0.05, 0.6, 0.05]);
+        let expected = dense().predict(filled.view());
+
+        // tags below the cutoff contribute as if their probabilities were half the cutoff
+        let sparse = dense().with_sparse_cutoff(0.1);
+        for (e, a) in expected.iter().zip(&sparse.predict(probs.view())) {
+            assert!((e - a).abs() < 1e-5, "{} vs {}", e, a);
+        }
+
+        let quantized = NaiveBayes::from_quantized(quantized_bytes())
+            .unwrap()
+            .with_sparse_cutoff(0.1);
+        let expected = NaiveBayes::from_quantized(quantized_bytes())
+            .unwrap()
+            .predict(filled.view());
+        for (e, a) in expected.iter().zip(&quantized.predict(probs.view())) {
+            assert!((e - a).abs() < 1e-5, "{} vs {}", e, a);
+        }
+    }
+
+    #[test]
+    fn from_bytes_detects_quantized() {
+        let naive_bayes = NaiveBayes::from_bytes(quantized_bytes()).unwrap();
+        assert!(matches!(naive_bayes.array_a, Weights::Quantized { .. }));
+    }
+
+    #[test]
+    fn parse_quantized_rejects_invalid_bytes() {
+        let is_invalid = |bytes: Vec<u8>| {
+            matches!(
+                NaiveBayes::from_quantized(bytes),
+                Err(Error::InvalidNaiveBayes)
+            )
+        };
+
+        let mut bad_magic = quantized_bytes();
+        bad_magic[0] = b'X';
+        assert!(is_invalid(bad_magic));
+
+        let mut bad_version = quantized_bytes();
+        bad_version[4..8].copy_from_slice(&2u32.to_le_bytes());
+        assert!(is_invalid(bad_version));
+
+        let mut truncated = quantized_bytes();
+        truncated.pop();
+        assert!(is_invalid(truncated));
+
+        let mut trailing = quantized_bytes();
+        trailing.push(0);
+        assert!(is_invalid(trailing));
+
+        assert!(is_invalid(
+            quantized_bytes()[..QUANTIZED_HEADER_LEN - 1].to_vec()
+        ));
+
+        // num_rows * num_cols wraps around to the actual length with 32-bit usize
+        let mut huge = quantized_bytes()[..QUANTIZED_HEADER_LEN].to_vec();
+        huge[8..12].copy_from_slice(&0x8000_0000u32.to_le_bytes());
+        huge[12..16].copy_from_slice(&2u32.to_le_bytes());
+        huge.extend_from_slice(&[0; 2 * 2 * 4]);
+        assert!(is_invalid(huge));
+
+        let mut huge = quantized_bytes()[..QUANTIZED_HEADER_LEN].to_vec();
+        huge[8..16].copy_from_slice(&[0xff; 8]);
+        assert!(is_invalid(huge));
     }
 }
@@ -1,108 +1,127 @@
-use crate::Result;
-
-use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageBuffer, Pixel, Rgb};
-use std::io::Read;
-use tract_onnx::{prelude::*, tract_core::ndarray};
-
-type TractModel = RunnableModel<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>;
+use super::preprocess::{Preprocessor, INPUT_SHAPE};
+use crate::{
+    backend::{InferenceBackend, TractBackend},
+    pixels::ImageRef,
+    Error, Result, Timings,
+};
+
+use image::DynamicImage;
+use instant::Instant;
+use std::{
+    cell::RefCell,
+    io::{Read, Write},
+};
+use tract_onnx::tract_core::ndarray::ArrayD;
 
 pub struct NeuralNet {
-    model: TractModel,
+    backend: Box<dyn InferenceBackend>,
 }
 
-const NUM_CHANNELS: usize = Rgb::<u8>::CHANNEL_COUNT as usize;
-const WIDTH: usize = 512;
-const HEIGHT: usize = 512;
+thread_local! {
+    // each thread keeps its own buffers so that predictions can run concurrently
+    static PREPROCESSOR: RefCell<Preprocessor> = RefCell::new(Preprocessor::new());
+}
 
 impl NeuralNet {
-    pub fn new<R: Read>(mut reader: R) -> Result<Self> {
-        let model = tract_onnx::onnx()
-            .model_for_read(&mut reader)?
-            .with_input_fact(
-                0,
-                InferenceFact::dt_shape(f32::datum_type(), tvec!(1, NUM_CHANNELS, HEIGHT, WIDTH)),
-            )?
-            .into_optimized()?
-            .into_runnable()?;
-
-        Ok(Self { model })
+    /// Loads an ONNX model with the tract backend.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "load_neural_net", skip(reader))
+    )]
+    pub fn new<R: Read>(reader: R) -> Result<Self> {
+        let backend = TractBackend::new(reader, INPUT_SHAPE, None)?;
+        Ok(Self::with_backend(backend))
     }
 
-    pub fn predict(&self, img: DynamicImage) -> Result<Arc<Tensor>> {
-        let resized = resize_and_pad_img(img, WIDTH as u32, HEIGHT as u32);
-
-        const NORM_SCALE: f32 = 1. / 255.;
-        let tensor =
-            ndarray::Array4::from_shape_fn((1, NUM_CHANNELS, HEIGHT, WIDTH), |(_, c, y, x)| {
-                resized[(x as _, y as _)][c] as f32 * NORM_SCALE
-            })
-            .into();
-
-        let output = self.model.run(tvec!(tensor))?;
-        Ok(output[0].clone())
+    /// Loads a model which additionally outputs the tensor of the node `embedding_node`
+    /// so that it can be used as an embedding of images.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "load_neural_net", skip(reader))
+    )]
+    pub fn with_embedding<R: Read>(reader: R, embedding_node: &str) -> Result<Self> {
+        let backend = TractBackend::new(reader, INPUT_SHAPE, Some(embedding_node))?;
+        Ok(Self::with_backend(backend))
     }
-}
 
-// based on https://github.com/image-rs/imageproc/blob/5a7a68bfe54d27d531edcadf16b032930fe1a54c/src/geometric_transformations.rs#L335-L376
-fn resize_and_pad_img(
-    img: DynamicImage,
-    target_width: u32,
-    target_height: u32,
-) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
-    let (tw, th) = (target_width, target_height);
-    if img.dimensions() == (tw, th) {
-        return img.into_rgb8();
+    /// Loads a model compiled with [`NeuralNet::compile`].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "load_neural_net", skip(reader))
+    )]
+    pub fn from_compiled<R: Read>(reader: R) -> Result<Self> {
+        let backend = TractBackend::from_compiled(reader)?;
+        Ok(Self::with_backend(backend))
     }
 
-    let img = img.resize(tw, th, FilterType::CatmullRom).into_rgb8();
-    let (w, h) = img.dimensions();
-    if w == tw && h == th {
-        return img;
+    /// Loads either an ONNX model or a compiled one, telling them apart by their contents.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        // compiled models are tar archives, which have the magic at offset 257
+        if bytes.get(257..262) == Some(&b"ustar"[..]) {
+            Self::from_compiled(bytes)
+        } else {
+            Self::new(bytes)
+        }
     }
 
-    let mut out = ImageBuffer::<Rgb<u8>, _>::new(tw, th);
-    if w < tw {
-        let margin = (tw as usize - w as usize) / 2;
-
-        for y in 0..th {
-            let p_min = *img.get_pixel(0, y);
-            for x in 0..margin as u32 {
-                out.put_pixel(x, y, p_min);
-            }
-
-            let in_base = y as usize * w as usize * NUM_CHANNELS;
-            let out_base = (margin + y as usize * tw as usize) * NUM_CHANNELS;
-            let len = w as usize * NUM_CHANNELS;
-            (*out)[out_base..][..len].copy_from_slice(&(*img)[in_base..][..len]);
+    /// Converts an ONNX model into a form which loads faster with [`NeuralNet::from_compiled`].
+    pub fn compile<R: Read, W: Write>(
+        reader: R,
+        writer: W,
+        embedding_node: Option<&str>,
+    ) -> Result<()> {
+        TractBackend::compile(reader, writer, INPUT_SHAPE, embedding_node)
+    }
 
-            let p_max = *img.get_pixel(w - 1, y);
-            for x in tw - margin as u32 - 1..tw {
-                out.put_pixel(x, y, p_max);
-            }
+    pub fn with_backend<B: InferenceBackend + 'static>(backend: B) -> Self {
+        Self {
+            backend: Box::new(backend),
         }
+    }
 
-        return out;
-    } else if h < th {
-        let margin = (th as usize - h as usize) / 2;
-        let line_len = tw as usize * NUM_CHANNELS;
-        let whole_len = w as usize * h as usize * NUM_CHANNELS;
-
-        for y in 0..margin {
-            let out_base = y * tw as usize * NUM_CHANNELS;
-            (*out)[out_base..][..line_len].copy_from_slice(&(*img)[..line_len]);
-        }
+    pub fn predict(&self, img: &DynamicImage) -> Result<ArrayD<f32>> {
+        let (prediction, _) =
+            self.predict_image(&ImageRef::Dynamic(img), &mut Timings::default())?;
+        Ok(prediction)
+    }
 
-        let out_base = margin * tw as usize * NUM_CHANNELS;
-        (*out)[out_base..][..whole_len].copy_from_slice(&(*img)[..whole_len]);
+    /// Returns the embedding along with the prediction if the neural net outputs one.
+    pub fn predict_with_embedding(
+        &self,
+        img: &DynamicImage,
+    ) -> Result<(ArrayD<f32>, Option<ArrayD<f32>>)> {
+        self.predict_image(&ImageRef::Dynamic(img), &mut Timings::default())
+    }
 
-        let in_base = (h as usize - 1) * tw as usize * NUM_CHANNELS;
-        for y in th as usize - margin - 1..th as usize {
-            let out_base = y * tw as usize * NUM_CHANNELS;
-            (*out)[out_base..][..line_len].copy_from_slice(&(*img)[in_base..][..line_len]);
-        }
+    /// Adds durations of preprocessing and inference to `timings`.
+    pub(crate) fn predict_image(
+        &self,
+        img: &ImageRef,
+        timings: &mut Timings,
+    ) -> Result<(ArrayD<f32>, Option<ArrayD<f32>>)> {
+        let mut output = self.run(img, timings)?.into_iter();
+        let prediction = output.next().ok_or(Error::MissingOutput)?;
+        Ok((prediction, output.next()))
+    }
 
-        return out;
+    /// Backend running the neural net, e.g. for timing it apart from preprocessing.
+    pub fn backend(&self) -> &dyn InferenceBackend {
+        self.backend.as_ref()
     }
 
-    unreachable!()
+    fn run(&self, img: &ImageRef, timings: &mut Timings) -> Result<Vec<ArrayD<f32>>> {
+        PREPROCESSOR.with(|preprocessor| {
+            let start = Instant::now();
+            let mut preprocessor = preprocessor.borrow_mut();
+            let tensor = preprocessor.run(img)?;
+            timings.preprocess += start.elapsed();
+
+            let start = Instant::now();
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("inference").entered();
+            let output = self.backend.run(tensor)?;
+            timings.network += start.elapsed();
+            Ok(output)
+        })
+    }
 }
@@ -0,0 +1,183 @@
+use crate::{pixels::ImageRef, Error, Result};
+
+use fast_image_resize as fr;
+use image::DynamicImage;
+use std::{borrow::Cow, num::NonZeroU32};
+use tract_onnx::tract_core::ndarray::{Array4, ArrayView4};
+
+pub(crate) const NUM_CHANNELS: usize = 3;
+pub(crate) const WIDTH: usize = 512;
+pub(crate) const HEIGHT: usize = 512;
+pub(crate) const INPUT_SHAPE: [usize; 4] = [1, NUM_CHANNELS, HEIGHT, WIDTH];
+
+const NORM_SCALE: f32 = 1. / 255.;
+
+/// Converts images into input tensors of the neural net.
+///
+/// Images are resized preserving the aspect ratio, padded by repeating the pixels on the edges,
+/// and written into a tensor which is allocated once and reused across calls.
+pub struct Preprocessor {
+    resizer: fr::Resizer,
+    resized: Option<fr::Image<'static>>,
+    tensor: Array4<f32>,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self {
+            resizer: fr::Resizer::new(fr::ResizeAlg::Convolution(fr::FilterType::CatmullRom)),
+            resized: None,
+            tensor: Array4::zeros((1, NUM_CHANNELS, HEIGHT, WIDTH)),
+        }
+    }
+
+    pub fn preprocess(&mut self, img: &DynamicImage) -> Result<ArrayView4<f32>> {
+        self.run(&ImageRef::Dynamic(img))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "preprocess",
+            skip(self, img),
+            fields(width = img.dimensions().0, height = img.dimensions().1)
+        )
+    )]
+    pub(crate) fn run(&mut self, img: &ImageRef) -> Result<ArrayView4<f32>> {
+        let src = Source::new(img);
+        let (width, height) = (
+            NonZeroU32::new(src.width).ok_or(Error::EmptyImage)?,
+            NonZeroU32::new(src.height).ok_or(Error::EmptyImage)?,
+        );
+
+        let (w, h) = resized_dimensions(src.width, src.height);
+        if (w, h) == (src.width, src.height) {
+            fill_tensor(&mut self.tensor, &src, &src.bytes, w, h);
+            return Ok(self.tensor.view());
+        }
+
+        let pixel_type = match src.channels {
+            3 => fr::PixelType::U8x3,
+            4 => fr::PixelType::U8x4,
+            _ => unreachable!(),
+        };
+        let (dst_width, dst_height) = (NonZeroU32::new(w).unwrap(), NonZeroU32::new(h).unwrap());
+
+        // reuse the destination buffer as long as the size and the pixel type stay the same
+        let resized = match self.resized.take() {
+            Some(resized)
+                if resized.width() == dst_width
+                    && resized.height() == dst_height
+                    && resized.pixel_type() == pixel_type =>
+            {
+                resized
+            }
+            _ => fr::Image::new(dst_width, dst_height, pixel_type),
+        };
+        let resized = self.resized.insert(resized);
+
+        let src_view = fr::ImageView::from_buffer(width, height, &src.bytes, pixel_type)
+            .expect("Buffer size should match the dimensions");
+        self.resizer
+            .resize(&src_view, &mut resized.view_mut())
+            .expect("Pixel types should be the same");
+
+        fill_tensor(&mut self.tensor, &src, resized.buffer(), w, h);
+        Ok(self.tensor.view())
+    }
+}
+
+/// Dimensions of an image after resizing it to fit in the input preserving the aspect ratio.
+fn resized_dimensions(width: u32, height: u32) -> (u32, u32) {
+    let ratio = (WIDTH as f64 / width as f64).min(HEIGHT as f64 / height as f64);
+    (
+        ((width as f64 * ratio).round() as u32).clamp(1, WIDTH as u32),
+        ((height as f64 * ratio).round() as u32).clamp(1, HEIGHT as u32),
+    )
+}
+
+/// Horizontal and vertical padding added to an image after resizing it.
+pub(crate) fn padding(width: u32, height: u32) -> (u32, u32) {
+    let (w, h) = resized_dimensions(width, height);
+    (WIDTH as u32 - w, HEIGHT as u32 - h)
+}
+
+/// Pixels of an image in a layout which the resizer accepts.
+struct Source<'a> {
+    bytes: Cow<'a, [u8]>,
+    width: u32,
+    height: u32,
+    channels: usize,
+
+    // indices of red, green and blue in a pixel
+    order: [usize; NUM_CHANNELS],
+}
+
+impl<'a> Source<'a> {
+    fn new(img: &'a ImageRef) -> Self {
+        const RGB: [usize; NUM_CHANNELS] = [0, 1, 2];
+        const BGR: [usize; NUM_CHANNELS] = [2, 1, 0];
+
+        let (width, height) = img.dimensions();
+        let (bytes, channels, order): (Cow<[u8]>, _, _) = match img {
+            ImageRef::Dynamic(DynamicImage::ImageRgb8(buf)) => (buf.as_raw().into(), 3, RGB),
+            ImageRef::Dynamic(DynamicImage::ImageRgba8(buf)) => (buf.as_raw().into(), 4, RGB),
+            ImageRef::Dynamic(DynamicImage::ImageBgr8(buf)) => (buf.as_raw().into(), 3, BGR),
+            ImageRef::Dynamic(DynamicImage::ImageBgra8(buf)) => (buf.as_raw().into(), 4, BGR),
+            ImageRef::Dynamic(img) => (img.to_rgb8().into_raw().into(), 3, RGB),
+            ImageRef::Rgb(buf) => ((*buf.as_raw()).into(), 3, RGB),
+            ImageRef::Rgba(buf) => ((*buf.as_raw()).into(), 4, RGB),
+            ImageRef::Bgra(buf) => ((*buf.as_raw()).into(), 4, BGR),
+        };
+
+        Self {
+            bytes,
+            width,
+            height,
+            channels,
+            order,
+        }
+    }
+}
+
+/// Writes normalized pixels of a `width` x `height` image into the center of the tensor,
+/// extending the edges of the image to the margins.
+fn fill_tensor(tensor: &mut Array4<f32>, src: &Source, pixels: &[u8], width: u32, height: u32) {
+    let (w, h) = (width as usize, height as usize);
+    let (margin_x, margin_y) = ((WIDTH - w) / 2, (HEIGHT - h) / 2);
+    let row_len = w * src.channels;
+
+    let data = tensor
+        .as_slice_mut()
+        .expect("Tensor should be in standard layout");
+    let (red, rest) = data.split_at_mut(WIDTH * HEIGHT);
+    let (green, blue) = rest.split_at_mut(WIDTH * HEIGHT);
+
+    for (y, ((red, green), blue)) in red
+        .chunks_exact_mut(WIDTH)
+        .zip(green.chunks_exact_mut(WIDTH))
+        .zip(blue.chunks_exact_mut(WIDTH))
+        .enumerate()
+    {
+        let src_y = y.saturating_sub(margin_y).min(h - 1);
+        let row = &pixels[src_y * row_len..][..row_len];
+        for (x, ((red, green), blue)) in red
+            .iter_mut()
+            .zip(green.iter_mut())
+            .zip(blue.iter_mut())
+            .enumerate()
+        {
+            let src_x = x.saturating_sub(margin_x).min(w - 1);
+            let pixel = &row[src_x * src.channels..][..src.channels];
+            *red = pixel[src.order[0]] as f32 * NORM_SCALE;
+            *green = pixel[src.order[1]] as f32 * NORM_SCALE;
+            *blue = pixel[src.order[2]] as f32 * NORM_SCALE;
+        }
+    }
+}
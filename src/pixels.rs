@@ -0,0 +1,104 @@
+use crate::{Error, Result};
+
+use image::{imageops, Bgra, DynamicImage, GenericImageView, ImageBuffer, Rgb, Rgba};
+
+/// Memory layout of a raw pixel buffer, with 8 bits per channel and no padding between rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb,
+    Rgba,
+    Bgra,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgb => 3,
+            Self::Rgba | Self::Bgra => 4,
+        }
+    }
+}
+
+/// Image borrowed from either a [`DynamicImage`] or a raw pixel buffer.
+pub(crate) enum ImageRef<'a> {
+    Dynamic(&'a DynamicImage),
+    Rgb(ImageBuffer<Rgb<u8>, &'a [u8]>),
+    Rgba(ImageBuffer<Rgba<u8>, &'a [u8]>),
+    Bgra(ImageBuffer<Bgra<u8>, &'a [u8]>),
+}
+
+impl<'a> ImageRef<'a> {
+    pub fn from_pixels(
+        pixels: &'a [u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<Self> {
+        let len = width as usize * height as usize * format.bytes_per_pixel();
+        if pixels.len() != len {
+            return Err(Error::PixelBufferSize);
+        }
+
+        // from_raw only fails if the buffer is too small, which is checked above
+        Ok(match format {
+            PixelFormat::Rgb => Self::Rgb(ImageBuffer::from_raw(width, height, pixels).unwrap()),
+            PixelFormat::Rgba => Self::Rgba(ImageBuffer::from_raw(width, height, pixels).unwrap()),
+            PixelFormat::Bgra => Self::Bgra(ImageBuffer::from_raw(width, height, pixels).unwrap()),
+        })
+    }
+
+    #[cfg(feature = "cache")]
+    pub fn color(&self) -> image::ColorType {
+        use image::ColorType;
+
+        match self {
+            Self::Dynamic(img) => img.color(),
+            Self::Rgb(_) => ColorType::Rgb8,
+            Self::Rgba(_) => ColorType::Rgba8,
+            Self::Bgra(_) => ColorType::Bgra8,
+        }
+    }
+
+    #[cfg(feature = "cache")]
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Dynamic(img) => img.as_bytes(),
+            Self::Rgb(buf) => buf.as_raw(),
+            Self::Rgba(buf) => buf.as_raw(),
+            Self::Bgra(buf) => buf.as_raw(),
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            Self::Dynamic(img) => img.dimensions(),
+            Self::Rgb(buf) => buf.dimensions(),
+            Self::Rgba(buf) => buf.dimensions(),
+            Self::Bgra(buf) => buf.dimensions(),
+        }
+    }
+
+    pub fn fliph(&self) -> DynamicImage {
+        match self {
+            Self::Dynamic(img) => img.fliph(),
+            Self::Rgb(buf) => DynamicImage::ImageRgb8(imageops::flip_horizontal(buf)),
+            Self::Rgba(buf) => DynamicImage::ImageRgba8(imageops::flip_horizontal(buf)),
+            Self::Bgra(buf) => DynamicImage::ImageBgra8(imageops::flip_horizontal(buf)),
+        }
+    }
+
+    pub fn crop_imm(&self, x: u32, y: u32, width: u32, height: u32) -> DynamicImage {
+        match self {
+            Self::Dynamic(img) => img.crop_imm(x, y, width, height),
+            Self::Rgb(buf) => {
+                DynamicImage::ImageRgb8(imageops::crop_imm(buf, x, y, width, height).to_image())
+            }
+            Self::Rgba(buf) => {
+                DynamicImage::ImageRgba8(imageops::crop_imm(buf, x, y, width, height).to_image())
+            }
+            Self::Bgra(buf) => {
+                DynamicImage::ImageBgra8(imageops::crop_imm(buf, x, y, width, height).to_image())
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{Classifier, Prediction, Result};
+
+use crossbeam_channel::Receiver;
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Instant,
+};
+
+/// Decodes and tags images on multiple threads sharing one [`Classifier`].
+pub struct TaggingPool<'a> {
+    classifier: &'a Classifier,
+    num_threads: usize,
+    queue_size: usize,
+}
+
+/// Results of [`TaggingPool::run`] in the order that images finish.
+pub struct TaggingResults<'a> {
+    receiver: Receiver<(PathBuf, Result<Prediction<'a>>)>,
+}
+
+impl<'a> Iterator for TaggingResults<'a> {
+    type Item = (PathBuf, Result<Prediction<'a>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'a> TaggingPool<'a> {
+    pub fn new(classifier: &'a Classifier, num_threads: usize) -> Self {
+        let num_threads = num_threads.max(1);
+        Self {
+            classifier,
+            num_threads,
+            queue_size: 2 * num_threads,
+        }
+    }
+
+    /// Sets how many results can wait to be consumed before workers stop taking new images.
+    pub fn with_queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size;
+        self
+    }
+
+    /// Tags images at `paths` and passes the stream of results to `f`.
+    ///
+    /// Workers stop once `f` drops the results, after finishing the images they are working on.
+    pub fn run<I, F, T>(&self, paths: I, f: F) -> T
+    where
+        I: IntoIterator<Item = PathBuf>,
+        I::IntoIter: Send,
+        F: FnOnce(TaggingResults<'a>) -> T,
+    {
+        let paths = Mutex::new(paths.into_iter());
+        let (sender, receiver) = crossbeam_channel::bounded(self.queue_size);
+
+        crossbeam_utils::thread::scope(|scope| {
+            for _ in 0..self.num_threads {
+                let sender = sender.clone();
+                let paths = &paths;
+                scope.spawn(move |_| loop {
+                    // release the lock before tagging so that other workers can proceed
+                    let path = match paths.lock().unwrap().next() {
+                        Some(path) => path,
+                        None => break,
+                    };
+                    let result = self.tag(&path);
+                    if sender.send((path, result)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+
+            f(TaggingResults { receiver })
+        })
+        .unwrap_or_else(|err| std::panic::resume_unwind(err))
+    }
+
+    fn tag(&self, path: &Path) -> Result<Prediction<'a>> {
+        let start = Instant::now();
+        let img = image::open(path)?;
+        let decode_time = start.elapsed();
+
+        let mut prediction = self.classifier.predict(img)?;
+        if let Some(timings) = prediction.timings_mut() {
+            timings.decode = decode_time;
+        }
+        Ok(prediction)
+    }
+}
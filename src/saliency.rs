@@ -0,0 +1,56 @@
+use crate::BoundingBox;
+
+use image::{DynamicImage, GenericImage, Rgba};
+
+pub struct Occlusion {
+    pub num_rows: u32,
+    pub num_cols: u32,
+}
+
+pub enum SaliencyTarget<'a> {
+    General(&'a str),
+    Character(&'a str),
+}
+
+impl Occlusion {
+    pub(crate) fn shape(&self, width: u32, height: u32) -> (usize, usize) {
+        (
+            self.num_rows.clamp(1, height.max(1)) as usize,
+            self.num_cols.clamp(1, width.max(1)) as usize,
+        )
+    }
+
+    // row-major
+    pub(crate) fn bboxes(&self, width: u32, height: u32) -> Vec<BoundingBox> {
+        let (num_rows, num_cols) = self.shape(width, height);
+        let edges = |len: u32, n: usize| -> Vec<u32> {
+            (0..=n as u64)
+                .map(|i| (i * len as u64 / n as u64) as u32)
+                .collect()
+        };
+        let (ys, xs) = (edges(height, num_rows), edges(width, num_cols));
+
+        ys.windows(2)
+            .flat_map(|y| {
+                xs.windows(2).map(move |x| BoundingBox {
+                    x: x[0],
+                    y: y[0],
+                    width: x[1] - x[0],
+                    height: y[1] - y[0],
+                })
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn occlude(img: &DynamicImage, bbox: &BoundingBox) -> DynamicImage {
+    const FILL: Rgba<u8> = Rgba([127, 127, 127, 255]);
+
+    let mut img = img.clone();
+    for y in bbox.y..bbox.y + bbox.height {
+        for x in bbox.x..bbox.x + bbox.width {
+            img.put_pixel(x, y, FILL);
+        }
+    }
+    img
+}
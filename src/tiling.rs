@@ -0,0 +1,124 @@
+use serde::Serialize;
+
+pub struct Tiling {
+    /// Side length of tiles in pixels, which is at least 1.
+    pub tile_size: u32,
+
+    /// Fraction of overlap between adjacent tiles, clamped to `0..=MAX_OVERLAP`.
+    pub overlap: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BoundingBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tiling {
+    /// Overlaps closer to 1 would make the stride a few pixels and the number of tiles explode.
+    pub const MAX_OVERLAP: f32 = 0.9;
+
+    pub(crate) fn bboxes(&self, width: u32, height: u32) -> Vec<BoundingBox> {
+        let tile_size = self.tile_size.max(1);
+        // negative overlaps would leave gaps between tiles
+        let overlap = if self.overlap.is_nan() {
+            0.
+        } else {
+            self.overlap.clamp(0., Self::MAX_OVERLAP)
+        };
+
+        let (tile_width, tile_height) = (tile_size.min(width), tile_size.min(height));
+        let stride = ((tile_size as f32 * (1. - overlap)).round() as u32).max(1);
+
+        let xs = offsets(width, tile_width, stride);
+        let ys = offsets(height, tile_height, stride);
+        ys.iter()
+            .flat_map(|&y| {
+                xs.iter().map(move |&x| BoundingBox {
+                    x,
+                    y,
+                    width: tile_width,
+                    height: tile_height,
+                })
+            })
+            .collect()
+    }
+}
+
+// offsets of tiles covering 0..len, with the last tile aligned to the end
+fn offsets(len: u32, tile_len: u32, stride: u32) -> Vec<u32> {
+    let last = len - tile_len;
+    let mut offsets: Vec<_> = (0..last).step_by(stride as usize).collect();
+    offsets.push(last);
+    offsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_cover_the_whole_length() {
+        assert_eq!(offsets(10, 4, 4), vec![0, 4, 6]);
+        assert_eq!(offsets(8, 4, 4), vec![0, 4]);
+        assert_eq!(offsets(10, 4, 3), vec![0, 3, 6]);
+        assert_eq!(offsets(4, 4, 2), vec![0]);
+    }
+
+    #[test]
+    fn bboxes_without_overlap() {
+        let tiling = Tiling {
+            tile_size: 50,
+            overlap: 0.,
+        };
+        let bboxes = tiling.bboxes(100, 60);
+        let offsets: Vec<_> = bboxes.iter().map(|bbox| (bbox.x, bbox.y)).collect();
+        assert_eq!(offsets, vec![(0, 0), (50, 0), (0, 10), (50, 10)]);
+        assert!(bboxes
+            .iter()
+            .all(|bbox| bbox.width == 50 && bbox.height == 50));
+    }
+
+    #[test]
+    fn bboxes_of_image_smaller_than_tile() {
+        let tiling = Tiling {
+            tile_size: 512,
+            overlap: 0.25,
+        };
+        assert_eq!(
+            tiling.bboxes(100, 80),
+            vec![BoundingBox {
+                x: 0,
+                y: 0,
+                width: 100,
+                height: 80
+            }]
+        );
+    }
+
+    #[test]
+    fn bboxes_clamp_parameters() {
+        let bboxes = |tile_size, overlap| Tiling { tile_size, overlap }.bboxes(1000, 1000);
+
+        // overlap of 1 or more is clamped instead of producing a 1-pixel stride
+        assert_eq!(bboxes(100, 1.), bboxes(100, Tiling::MAX_OVERLAP));
+        assert_eq!(bboxes(100, 5.).len(), 91 * 91);
+
+        // negative overlap would leave gaps
+        assert_eq!(bboxes(100, -1.), bboxes(100, 0.));
+        assert_eq!(bboxes(100, f32::NAN), bboxes(100, 0.));
+
+        // zero-sized tiles are grown to one pixel
+        let tiling = Tiling {
+            tile_size: 0,
+            overlap: 0.,
+        };
+        let bboxes = tiling.bboxes(3, 2);
+        assert_eq!(bboxes.len(), 6);
+        assert!(bboxes
+            .iter()
+            .all(|bbox| bbox.width == 1 && bbox.height == 1));
+    }
+}
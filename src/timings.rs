@@ -0,0 +1,75 @@
+use serde::{Serialize, Serializer};
+use std::{fmt, time::Duration};
+
+/// Durations of the stages of a prediction along with the shape of the input.
+///
+/// Durations are summed over all augmented views and serialized in milliseconds.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Timings {
+    /// Time spent decoding the image, which is left zero by [`Classifier`](crate::Classifier)
+    /// for callers to fill in.
+    #[serde(serialize_with = "millis")]
+    pub decode: Duration,
+
+    /// Resizing, padding and normalization.
+    #[serde(serialize_with = "millis")]
+    pub preprocess: Duration,
+
+    /// Inference of the neural net.
+    #[serde(serialize_with = "millis")]
+    pub network: Duration,
+
+    /// Naive Bayes and calibration.
+    #[serde(serialize_with = "millis")]
+    pub head: Duration,
+
+    pub width: u32,
+    pub height: u32,
+
+    /// Horizontal and vertical padding added to the resized image, in pixels.
+    pub padding: (u32, u32),
+
+    /// Whether the outputs of the neural net were read from the cache.
+    pub cache_hit: bool,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.decode + self.preprocess + self.network + self.head
+    }
+
+    /// Adds the time spent on another inference, such as that of a tile.
+    pub(crate) fn add(&mut self, other: &Timings) {
+        self.preprocess += other.preprocess;
+        self.network += other.network;
+        self.head += other.head;
+        self.cache_hit &= other.cache_hit;
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "decode {:.1?}, preprocess {:.1?}, network {:.1?}, head {:.1?} (total {:.1?}), \
+             input {}x{}, padding {}x{}",
+            self.decode,
+            self.preprocess,
+            self.network,
+            self.head,
+            self.total(),
+            self.width,
+            self.height,
+            self.padding.0,
+            self.padding.1
+        )?;
+        if self.cache_hit {
+            f.write_str(", cache hit")?;
+        }
+        Ok(())
+    }
+}
+
+fn millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.)
+}
@@ -0,0 +1,317 @@
+use crate::{
+    files::{self, InputOpt},
+    ModelOpt,
+};
+
+use witchbooru::{Classifier, Tag};
+
+use anyhow::anyhow;
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct Opt {
+    #[structopt(flatten)]
+    input: InputOpt,
+
+    #[structopt(flatten)]
+    model: ModelOpt,
+
+    #[structopt(short = "k", long, default_value = "50")]
+    topk: usize,
+
+    /// Only write tags with scores above the threshold
+    #[structopt(short, long, default_value = "0.35")]
+    threshold: f32,
+
+    /// Extension of the sidecar files, which replaces that of the image
+    #[structopt(long, default_value = "txt")]
+    sidecar_extension: String,
+
+    /// String between tags
+    #[structopt(long, default_value = ", ")]
+    separator: String,
+
+    /// Order of tags within each category
+    #[structopt(long, default_value = "score", possible_values = TagOrder::VARIANTS)]
+    order: TagOrder,
+
+    /// Write character tags before general tags
+    #[structopt(long)]
+    characters_first: bool,
+
+    /// Replace underscores in tags with spaces
+    #[structopt(long)]
+    spaces: bool,
+
+    /// What to do when a sidecar file already exists
+    #[structopt(long, default_value = "skip", possible_values = Existing::VARIANTS)]
+    existing: Existing,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TagOrder {
+    Score,
+    Name,
+}
+
+impl TagOrder {
+    pub const VARIANTS: &'static [&'static str] = &["score", "name"];
+}
+
+impl FromStr for TagOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "score" => Ok(Self::Score),
+            "name" => Ok(Self::Name),
+            _ => Err(format!("Unknown order: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Existing {
+    /// Leave the file and don't tag the image
+    Skip,
+    /// Replace the file
+    Overwrite,
+    /// Append new tags to those already in the file
+    Merge,
+}
+
+impl Existing {
+    pub const VARIANTS: &'static [&'static str] = &["skip", "overwrite", "merge"];
+}
+
+impl FromStr for Existing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "overwrite" => Ok(Self::Overwrite),
+            "merge" => Ok(Self::Merge),
+            _ => Err(format!("Unknown policy for existing files: {}", s)),
+        }
+    }
+}
+
+pub fn run(opt: Opt) -> anyhow::Result<()> {
+    let paths = opt.input.collect_images()?;
+    let collisions = colliding_sidecars(&paths, &opt.sidecar_extension);
+    let classifier = crate::load_classifier(&opt.model, opt.topk, None)?;
+
+    let (mut num_written, mut num_skipped, mut num_failed) = (0, 0, 0);
+    for path in &paths {
+        let result = match collisions.get(path.as_path()) {
+            Some(sidecar_path) => Err(anyhow!(
+                "{} is also the sidecar file of another image",
+                sidecar_path.display()
+            )),
+            None => caption(&classifier, &opt, path),
+        };
+        match result {
+            Ok(true) => num_written += 1,
+            Ok(false) => num_skipped += 1,
+            Err(err) => {
+                eprintln!("Error: {}: {}", path.display(), err);
+                num_failed += 1;
+            }
+        }
+    }
+    eprintln!(
+        "Wrote {} sidecar files, skipped {} existing",
+        num_written, num_skipped
+    );
+
+    if num_failed > 0 {
+        return Err(anyhow!(
+            "Failed to caption {} of {} images",
+            num_failed,
+            paths.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Writes the sidecar file of the image at `path`, returning whether it was written.
+fn caption(classifier: &Classifier, opt: &Opt, path: &Path) -> anyhow::Result<bool> {
+    if files::is_remote(path) {
+        return Err(anyhow!(
+            "Sidecar files can only be written next to local images"
+        ));
+    }
+
+    let sidecar_path = sidecar_path(path, &opt.sidecar_extension);
+    let existing = if sidecar_path.exists() {
+        match opt.existing {
+            Existing::Skip => return Ok(false),
+            Existing::Overwrite => Vec::new(),
+            Existing::Merge => parse_tags(&fs::read_to_string(&sidecar_path)?, &opt.separator),
+        }
+    } else {
+        Vec::new()
+    };
+
+    let prediction = classifier.predict(files::open_image(path)?)?;
+    let (general, character) = (
+        select_tags(prediction.general(), opt),
+        select_tags(prediction.character(), opt),
+    );
+    let new_tags = if opt.characters_first {
+        character.into_iter().chain(general)
+    } else {
+        general.into_iter().chain(character)
+    };
+
+    let tags = merge_tags(existing, new_tags);
+
+    // an interrupted write must not truncate captions that were edited by hand
+    files::write_atomically(&sidecar_path, |writer| {
+        writeln!(writer, "{}", tags.join(&opt.separator))?;
+        Ok(())
+    })?;
+    Ok(true)
+}
+
+fn sidecar_path(path: &Path, extension: &str) -> PathBuf {
+    path.with_extension(extension.trim_start_matches('.'))
+}
+
+/// Maps images sharing a sidecar file with another image, e.g. `a.png` and `a.jpg`,
+/// to the sidecar file, which none of them is allowed to write.
+fn colliding_sidecars<'a>(paths: &'a [PathBuf], extension: &str) -> HashMap<&'a Path, PathBuf> {
+    let mut images: HashMap<PathBuf, Vec<&Path>> = HashMap::new();
+    for path in paths.iter().filter(|path| !files::is_remote(path)) {
+        images
+            .entry(sidecar_path(path, extension))
+            .or_default()
+            .push(path);
+    }
+
+    let mut collisions = HashMap::new();
+    for (sidecar_path, images) in images {
+        // the same image given twice is captioned twice, which is harmless
+        if images.iter().any(|path| *path != images[0]) {
+            for path in images {
+                collisions.insert(path, sidecar_path.clone());
+            }
+        }
+    }
+    collisions
+}
+
+fn parse_tags(contents: &str, separator: &str) -> Vec<String> {
+    contents
+        .split(existing_separator(separator))
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Appends new tags which are not in `existing` yet.
+fn merge_tags<I: IntoIterator<Item = String>>(existing: Vec<String>, new_tags: I) -> Vec<String> {
+    // existing tags keep their positions, which may have been edited by hand
+    let mut tags = existing;
+    for tag in new_tags {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+// lenient about whitespace around the separator in files that were edited by hand
+fn existing_separator(separator: &str) -> &str {
+    match separator.trim() {
+        "" => separator,
+        trimmed => trimmed,
+    }
+}
+
+fn select_tags(tags: &[Tag], opt: &Opt) -> Vec<String> {
+    let mut tags: Vec<_> = tags
+        .iter()
+        .filter(|tag| tag.score > opt.threshold)
+        .map(|tag| {
+            if opt.spaces {
+                tag.name.replace('_', " ")
+            } else {
+                tag.name.to_owned()
+            }
+        })
+        .collect();
+
+    // tags are already sorted by score
+    if opt.order == TagOrder::Name {
+        tags.sort();
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opt(args: &[&str]) -> Opt {
+        let required = ["caption", "img.png", "-m", "model"];
+        Opt::from_iter_safe(required.iter().chain(args)).unwrap()
+    }
+
+    fn tag(name: &str, score: f32) -> Tag {
+        Tag { name, score }
+    }
+
+    #[test]
+    fn select_tags_by_threshold_and_order() {
+        let tags = [tag("long_hair", 0.9), tag("1girl", 0.5), tag("solo", 0.2)];
+        assert_eq!(
+            select_tags(&tags, &opt(&["--threshold", "0.3"])),
+            vec!["long_hair", "1girl"]
+        );
+        assert_eq!(
+            select_tags(
+                &tags,
+                &opt(&["--threshold", "0.1", "--order", "name", "--spaces"])
+            ),
+            vec!["1girl", "long hair", "solo"]
+        );
+    }
+
+    #[test]
+    fn merge_keeps_existing_tags_first() {
+        let existing = parse_tags("solo ,1girl,, smile\n", ", ");
+        assert_eq!(existing, vec!["solo", "1girl", "smile"]);
+
+        let new_tags = vec![
+            "1girl".to_owned(),
+            "long_hair".to_owned(),
+            "solo".to_owned(),
+        ];
+        assert_eq!(
+            merge_tags(existing, new_tags),
+            vec!["solo", "1girl", "smile", "long_hair"]
+        );
+    }
+
+    #[test]
+    fn sidecars_of_images_with_the_same_stem_collide() {
+        let paths: Vec<PathBuf> = ["a.png", "a.jpg", "b.png", "b.png", "c.png", "-"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let collisions = colliding_sidecars(&paths, ".txt");
+        assert_eq!(collisions.len(), 2);
+        assert_eq!(collisions[Path::new("a.png")], PathBuf::from("a.txt"));
+        assert_eq!(collisions[Path::new("a.jpg")], PathBuf::from("a.txt"));
+    }
+}
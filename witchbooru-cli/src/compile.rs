@@ -0,0 +1,52 @@
+use crate::files;
+
+use witchbooru::{
+    model_dir,
+    models::{NaiveBayes, NeuralNet},
+};
+
+use std::{fs::File, io::BufReader, path::PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct Opt {
+    #[structopt(short, long)]
+    model: PathBuf,
+
+    /// Also output the tensor of the given node as an embedding
+    #[structopt(long)]
+    embedding_node: Option<String>,
+}
+
+#[derive(StructOpt)]
+pub struct QuantizeOpt {
+    #[structopt(short, long)]
+    model: PathBuf,
+}
+
+pub fn run(opt: Opt) -> anyhow::Result<()> {
+    let output = opt.model.join(model_dir::COMPILED_NEURAL_NET);
+    let input = BufReader::new(File::open(opt.model.join(model_dir::NEURAL_NET))?);
+    // the loader prefers the compiled neural net, so a truncated one must never be left behind
+    files::write_atomically(&output, |writer| {
+        Ok(NeuralNet::compile(
+            input,
+            writer,
+            opt.embedding_node.as_deref(),
+        )?)
+    })?;
+    eprintln!("Wrote {}", output.display());
+
+    Ok(())
+}
+
+pub fn quantize(opt: QuantizeOpt) -> anyhow::Result<()> {
+    let output = opt.model.join(model_dir::QUANTIZED_NAIVE_BAYES);
+    let naive_bayes = NaiveBayes::new(BufReader::new(File::open(
+        opt.model.join(model_dir::NAIVE_BAYES),
+    )?))?;
+    files::write_atomically(&output, |writer| Ok(naive_bayes.write_quantized(writer)?))?;
+    eprintln!("Wrote {}", output.display());
+
+    Ok(())
+}
@@ -0,0 +1,263 @@
+use witchbooru::image::{self, DynamicImage, ImageFormat};
+
+use anyhow::anyhow;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use structopt::StructOpt;
+
+/// Argument that reads image bytes from stdin.
+pub const STDIN: &str = "-";
+
+// same limits as witchbooru-lambda
+const DOWNLOAD_TIMEOUT_SECS: u64 = 5;
+const MAX_DOWNLOAD_SIZE: u64 = 8 * 1024 * 1024; // 8 MiB
+
+// formats enabled in the `image` features of witchbooru, since `ImageFormat::can_read`
+// also returns true for formats whose features are disabled
+const IMAGE_FORMATS: &[ImageFormat] = &[
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::Gif,
+    ImageFormat::Bmp,
+];
+
+#[derive(StructOpt)]
+pub struct InputOpt {
+    /// Image files, directories, glob patterns, HTTP(S) URLs, or `-` to read from stdin
+    #[structopt(required = true)]
+    images: Vec<PathBuf>,
+
+    /// Also include images in subdirectories of the given directories
+    #[structopt(short, long)]
+    recursive: bool,
+
+    /// Only include files with these extensions from directories and glob patterns
+    // without `require_delimiter`, the option would take the image paths that follow it
+    #[structopt(long, require_delimiter = true)]
+    extensions: Vec<String>,
+}
+
+impl InputOpt {
+    pub fn collect_images(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let paths = collect_images(&self.images, self.recursive, &self.extensions)?;
+        if paths.is_empty() {
+            return Err(anyhow!("No images found"));
+        }
+        Ok(paths)
+    }
+}
+
+/// Image that is read but not decoded yet.
+pub enum Source<'a> {
+    File(&'a Path),
+    Bytes(Vec<u8>),
+}
+
+impl Source<'_> {
+    /// Decodes the image, guessing the format of bytes from their content.
+    pub fn decode(self) -> anyhow::Result<DynamicImage> {
+        Ok(match self {
+            Source::File(path) => image::open(path)?,
+            Source::Bytes(bytes) => image::load_from_memory(&bytes)?,
+        })
+    }
+}
+
+/// Reads bytes from stdin if `path` is `-`, downloads them if it is an HTTP(S) URL,
+/// or leaves a local file to be opened on decoding.
+pub fn read_image(path: &Path) -> anyhow::Result<Source> {
+    match path.to_str() {
+        Some(STDIN) => {
+            let mut buf = Vec::new();
+            io::stdin().lock().read_to_end(&mut buf)?;
+            Ok(Source::Bytes(buf))
+        }
+        Some(url) if is_url(url) => download(url).map(Source::Bytes),
+        _ => Ok(Source::File(path)),
+    }
+}
+
+pub fn open_image(path: &Path) -> anyhow::Result<DynamicImage> {
+    read_image(path)?.decode()
+}
+
+/// Whether `path` is read from somewhere other than the local filesystem.
+pub fn is_remote(path: &Path) -> bool {
+    path.to_str()
+        .map(|s| s == STDIN || is_url(s))
+        .unwrap_or(false)
+}
+
+fn is_url(s: &str) -> bool {
+    s.starts_with("http://") || s.starts_with("https://")
+}
+
+fn download(url: &str) -> anyhow::Result<Vec<u8>> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(DOWNLOAD_TIMEOUT_SECS))
+        .build()?;
+    let response = client.get(url).send()?.error_for_status()?;
+    if response
+        .content_length()
+        .map(|len| len > MAX_DOWNLOAD_SIZE)
+        .unwrap_or(false)
+    {
+        return Err(anyhow!("Image is too large"));
+    }
+
+    // the server may not send Content-Length or may lie about it
+    let mut buf = Vec::new();
+    response.take(MAX_DOWNLOAD_SIZE + 1).read_to_end(&mut buf)?;
+    if buf.len() as u64 > MAX_DOWNLOAD_SIZE {
+        return Err(anyhow!("Image is too large"));
+    }
+    Ok(buf)
+}
+
+/// Expands files, directories and glob patterns into paths of images in the order given.
+///
+/// Files named explicitly, stdin and URLs are kept as they are so that errors are reported
+/// when they are read.
+fn collect_images(
+    args: &[PathBuf],
+    recursive: bool,
+    extensions: &[String],
+) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for arg in args {
+        if is_remote(arg) {
+            paths.push(arg.clone());
+            continue;
+        }
+        if arg.is_dir() {
+            list_images(arg, recursive, extensions, &mut paths)?;
+            continue;
+        }
+
+        let pattern = arg
+            .to_str()
+            .filter(|s| !arg.exists() && s.contains(&['*', '?', '['][..]));
+        if let Some(pattern) = pattern {
+            let num_paths = paths.len();
+            for path in glob::glob(pattern)? {
+                let path = path?;
+                if path.is_dir() {
+                    list_images(&path, recursive, extensions, &mut paths)?;
+                } else if is_image(&path, extensions) {
+                    paths.push(path);
+                }
+            }
+            if paths.len() == num_paths {
+                eprintln!("No images match {}", pattern);
+            }
+        } else {
+            paths.push(arg.clone());
+        }
+    }
+    Ok(paths)
+}
+
+/// Lists images in `dir` sorted by name, descending into subdirectories if `recursive`.
+///
+/// Images are files with one of `extensions`, or with the extension of any format that can
+/// be decoded if `extensions` is empty.
+pub fn list_images(
+    dir: &Path,
+    recursive: bool,
+    extensions: &[String],
+    paths: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            if recursive {
+                list_images(&path, recursive, extensions, paths)?;
+            }
+        } else if is_image(&path, extensions) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_image(path: &Path, extensions: &[String]) -> bool {
+    if extensions.is_empty() {
+        return ImageFormat::from_path(path)
+            .map_or(false, |format| IMAGE_FORMATS.contains(&format));
+    }
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| extensions.iter().any(|x| x.eq_ignore_ascii_case(ext)))
+        .unwrap_or(false)
+}
+
+/// Writes the file at `path` through a temporary file next to it, so that a failed or
+/// interrupted write never leaves a truncated file behind.
+pub fn write_atomically<F>(path: &Path, f: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> anyhow::Result<()>,
+{
+    let mut tmp_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid output path: {}", path.display()))?
+        .to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = (|| {
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        f(&mut writer)?;
+        // dropping the writer would swallow errors of the last flush
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(StructOpt)]
+    struct Opt {
+        #[structopt(flatten)]
+        input: InputOpt,
+
+        #[structopt(short, long)]
+        model: PathBuf,
+    }
+
+    #[test]
+    fn extensions_do_not_take_images() {
+        let opt =
+            Opt::from_iter_safe(&["predict", "--extensions", "jpg,png", "dir", "-m", "model"])
+                .unwrap();
+        assert_eq!(opt.input.extensions, vec!["jpg", "png"]);
+        assert_eq!(opt.input.images, vec![PathBuf::from("dir")]);
+        assert_eq!(opt.model, PathBuf::from("model"));
+    }
+
+    #[test]
+    fn only_decodable_formats_are_images() {
+        assert!(is_image(Path::new("a.PNG"), &[]));
+        assert!(is_image(Path::new("dir/a.jpeg"), &[]));
+        assert!(!is_image(Path::new("a.webp"), &[]));
+        assert!(!is_image(Path::new("a.tiff"), &[]));
+        assert!(!is_image(Path::new("a.txt"), &[]));
+        assert!(is_image(Path::new("a.webp"), &["webp".to_owned()]));
+        assert!(!is_image(Path::new("a.png"), &["jpg".to_owned()]));
+    }
+}
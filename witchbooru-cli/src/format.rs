@@ -1,16 +1,21 @@
-use witchbooru::{Prediction, Tag};
+use witchbooru::{Prediction, Tag, TilePrediction};
 
 use itertools::{EitherOrBoth, Itertools, Position};
 use std::fmt;
 
-pub struct Display<'a>(pub &'a Prediction<'a>);
+pub struct Display<'a> {
+    pub prediction: &'a Prediction<'a>,
+
+    /// Link tags to their wiki pages with OSC 8 escape sequences
+    pub hyperlinks: bool,
+}
 
 impl fmt::Display for Display<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         const SCORE_WIDTH: usize = 14;
         const SEPARATOR: &str = "─";
 
-        let (left, right) = (self.0.general(), self.0.character());
+        let (left, right) = (self.prediction.general(), self.prediction.character());
         let (left_category, right_category) = ("General tag", "Character");
         let (left_name_width, right_name_width) = (
             left.iter()
@@ -39,10 +44,10 @@ impl fmt::Display for Display<'_> {
 
         let (left, right) = (
             left.iter()
-                .map(|tag| format_tag(tag, left_name_width, SCORE_WIDTH)),
+                .map(|tag| format_tag(tag, left_name_width, SCORE_WIDTH, self.hyperlinks)),
             right
                 .iter()
-                .map(|tag| format_tag(tag, right_name_width, SCORE_WIDTH)),
+                .map(|tag| format_tag(tag, right_name_width, SCORE_WIDTH, self.hyperlinks)),
         );
         for row in left
             .zip_longest(right)
@@ -69,7 +74,27 @@ impl fmt::Display for Display<'_> {
     }
 }
 
-fn format_tag(tag: &Tag, name_width: usize, score_width: usize) -> String {
+pub struct TileDisplay<'a>(pub &'a TilePrediction<'a>);
+
+impl fmt::Display for TileDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const NUM_CHARACTERS: usize = 3;
+
+        let bbox = &self.0.bbox;
+        write!(
+            f,
+            "({}, {}) {}x{}:",
+            bbox.x, bbox.y, bbox.width, bbox.height
+        )?;
+        for tag in self.0.prediction.character().iter().take(NUM_CHARACTERS) {
+            write!(f, " {} {:.3}", tag.name, tag.score)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn format_tag(tag: &Tag, name_width: usize, score_width: usize, hyperlinks: bool) -> String {
     use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 
     // equivalent of encodeURIComponent()
@@ -101,14 +126,19 @@ fn format_tag(tag: &Tag, name_width: usize, score_width: usize) -> String {
 
     const SCORE_BAR_WIDTH: usize = 8;
 
-    let url = format!(
-        "https://danbooru.donmai.us/wiki_pages/{}",
-        utf8_percent_encode(tag.name, &ESCAPED)
-    );
-    let name = Hyperlink {
-        text: tag.name,
-        url: &url,
-        width: name_width,
+    let name = if hyperlinks {
+        let url = format!(
+            "https://danbooru.donmai.us/wiki_pages/{}",
+            utf8_percent_encode(tag.name, &ESCAPED)
+        );
+        Hyperlink {
+            text: tag.name,
+            url: &url,
+            width: name_width,
+        }
+        .to_string()
+    } else {
+        format!("{:width$}", tag.name, width = name_width)
     };
     let score_bar_len = (tag.score * SCORE_BAR_WIDTH as f32).round() as usize;
 
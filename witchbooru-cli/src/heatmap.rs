@@ -0,0 +1,36 @@
+use witchbooru::{
+    image::{
+        imageops::{self, FilterType},
+        DynamicImage, GenericImageView, ImageBuffer, Luma, RgbImage,
+    },
+    ndarray::ArrayView2,
+};
+
+pub fn overlay(img: &DynamicImage, saliency: ArrayView2<f32>) -> RgbImage {
+    const COLOR: [f32; 3] = [255., 0., 0.];
+    const OPACITY: f32 = 0.6;
+
+    // only regions supporting the target are highlighted
+    let max = saliency.iter().fold(0f32, |m, &x| m.max(x));
+    let (num_rows, num_cols) = saliency.dim();
+    let grid = ImageBuffer::from_fn(num_cols as u32, num_rows as u32, |x, y| {
+        let value = if max > 0. {
+            saliency[(y as usize, x as usize)].max(0.) / max
+        } else {
+            0.
+        };
+        Luma([(value * 255.).round() as u8])
+    });
+
+    let (width, height) = img.dimensions();
+    let heatmap = imageops::resize(&grid, width, height, FilterType::Triangle);
+
+    let mut out = img.to_rgb8();
+    for (pixel, value) in out.pixels_mut().zip(heatmap.pixels()) {
+        let alpha = value[0] as f32 / 255. * OPACITY;
+        for (c, target) in pixel.0.iter_mut().zip(COLOR.iter()) {
+            *c = (*c as f32 * (1. - alpha) + target * alpha).round() as u8;
+        }
+    }
+    out
+}
@@ -1,49 +1,171 @@
+mod caption;
+mod compile;
+mod files;
 mod format;
+mod heatmap;
+mod output;
+mod predict;
+mod similarity;
 
 use witchbooru::{
-    image,
-    models::{NaiveBayes, NeuralNet},
-    Classifier, Params,
+    cache::{self, Cache},
+    models::NeuralNet,
+    Augmentation, Classifier, ModelDir, Params,
 };
 
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::{Path, PathBuf},
-};
+use std::{ffi::OsString, fs, path::PathBuf};
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
-struct Opt {
-    image: PathBuf,
+enum Opt {
+    /// Predict general tags and characters of an image
+    Predict(predict::Opt),
+
+    /// Index images in a directory for similarity search
+    Index(similarity::IndexOpt),
+
+    /// Find indexed images similar to an image
+    Search(similarity::SearchOpt),
+
+    /// Write tags of images to sidecar text files next to them
+    Caption(caption::Opt),
+
+    /// Compile the neural net so that it loads faster
+    CompileModel(compile::Opt),
+
+    /// Quantize the naive Bayes model so that it is smaller and can be memory-mapped
+    QuantizeNaiveBayes(compile::QuantizeOpt),
+}
 
+#[derive(StructOpt)]
+struct ModelOpt {
     #[structopt(short, long)]
     model: PathBuf,
 
-    #[structopt(short = "k", long, default_value = "20")]
-    topk: usize,
+    /// Directory of the cache of neural net outputs
+    #[structopt(long)]
+    cache: Option<PathBuf>,
+
+    /// Score characters using only general tags with probabilities above the cutoff,
+    /// which is faster but approximate
+    #[structopt(long)]
+    sparse_cutoff: Option<f32>,
+
+    /// Also output the tensor of the given node of the ONNX model as an embedding
+    #[structopt(long)]
+    embedding_node: Option<String>,
+
+    /// Run the neural net with ONNX Runtime instead of tract
+    #[cfg(feature = "onnxruntime")]
+    #[structopt(long, conflicts_with = "embedding_node")]
+    onnxruntime: bool,
 }
 
+const SUBCOMMANDS: &[&str] = &[
+    "predict",
+    "index",
+    "search",
+    "caption",
+    "compile-model",
+    "quantize-naive-bayes",
+    "help",
+];
+
 fn main() -> anyhow::Result<()> {
-    let opt = Opt::from_args();
+    match Opt::from_iter(args()) {
+        Opt::Predict(opt) => predict::run(opt),
+        Opt::Index(opt) => similarity::index(opt),
+        Opt::Search(opt) => similarity::search(opt),
+        Opt::Caption(opt) => caption::run(opt),
+        Opt::CompileModel(opt) => compile::run(opt),
+        Opt::QuantizeNaiveBayes(opt) => compile::quantize(opt),
+    }
+}
 
-    let params = Params {
-        neural_net: NeuralNet::new(File::open(opt.model.join("neural-net.onnx"))?)?,
-        naive_bayes: NaiveBayes::new(File::open(opt.model.join("naive-bayes.npz"))?)?,
-        general_tags: read_list(opt.model.join("general-tags.txt"))?,
-        character_tags: read_list(opt.model.join("character-tags.txt"))?,
-        topk: opt.topk,
+fn load_classifier(
+    opt: &ModelOpt,
+    topk: usize,
+    augmentation: Option<Augmentation>,
+) -> anyhow::Result<Classifier> {
+    let dir = ModelDir::new(&opt.model);
+    for path in dir.stale_files() {
+        eprintln!(
+            "Warning: ignoring {} since it is older than the model it was created from",
+            path.display()
+        );
+    }
+
+    // the embedding node of the compiled neural net is fixed when it is compiled
+    #[cfg(feature = "onnxruntime")]
+    let is_onnx = opt.onnxruntime || opt.embedding_node.is_some();
+    #[cfg(not(feature = "onnxruntime"))]
+    let is_onnx = opt.embedding_node.is_some();
+    let neural_net_path = if is_onnx {
+        dir.join(witchbooru::model_dir::NEURAL_NET)
+    } else {
+        dir.neural_net_path()
     };
-    let classifier = Classifier::new(params)?;
+    let neural_net = fs::read(neural_net_path)?;
+    let general_tags = dir.general_tags()?;
 
-    let img = image::open(&opt.image)?;
-    let prediction = classifier.predict(img)?;
+    let cache = match &opt.cache {
+        Some(path) => {
+            let version = cache::model_version(
+                &cache::fingerprint(&neural_net),
+                opt.embedding_node.as_deref(),
+                &general_tags,
+            );
+            Some(Cache::open(path, &version)?)
+        }
+        None => None,
+    };
 
-    println!("{}", format::Display(&prediction));
+    let naive_bayes = dir.naive_bayes()?;
+    let naive_bayes = match opt.sparse_cutoff {
+        Some(cutoff) => naive_bayes.with_sparse_cutoff(cutoff),
+        None => naive_bayes,
+    };
+
+    let params = Params {
+        neural_net: load_neural_net(opt, &neural_net)?,
+        naive_bayes,
+        calibration: dir.calibration()?,
+        general_tags,
+        character_tags: dir.character_tags()?,
+        topk,
+        augmentation,
+    };
+
+    let classifier = Classifier::new(params)?;
+    Ok(match cache {
+        Some(cache) => classifier.with_cache(cache),
+        None => classifier,
+    })
+}
 
-    Ok(())
+fn load_neural_net(opt: &ModelOpt, bytes: &[u8]) -> anyhow::Result<NeuralNet> {
+    #[cfg(feature = "onnxruntime")]
+    if opt.onnxruntime {
+        use witchbooru::backend::OnnxRuntimeBackend;
+        return Ok(NeuralNet::with_backend(OnnxRuntimeBackend::new(bytes, 0)?));
+    }
+    if let Some(node) = &opt.embedding_node {
+        return Ok(NeuralNet::with_embedding(bytes, node)?);
+    }
+    Ok(NeuralNet::from_bytes(bytes)?)
 }
 
-fn read_list<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<String>> {
-    BufReader::new(File::open(path)?).lines().collect()
+// `witchbooru-cli IMAGE -m MODEL` predates the subcommands and still means `predict`
+fn args() -> Vec<OsString> {
+    let mut args: Vec<_> = std::env::args_os().collect();
+    let implicit_predict = args.get(1).map_or(false, |arg| match arg.to_str() {
+        Some(arg) => {
+            !(SUBCOMMANDS.contains(&arg) || ["-h", "--help", "-V", "--version"].contains(&arg))
+        }
+        None => true,
+    });
+    if implicit_predict {
+        args.insert(1, "predict".into());
+    }
+    args
 }
@@ -0,0 +1,292 @@
+use crate::format;
+
+use witchbooru::{BoundingBox, Prediction, Tag, TilePrediction, Timings, View};
+
+use itertools::Itertools;
+use serde::Serialize;
+use std::{borrow::Cow, path::Path, str::FromStr};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Ndjson,
+    Csv,
+    Tsv,
+    Plain,
+}
+
+impl OutputFormat {
+    pub const VARIANTS: &'static [&'static str] =
+        &["table", "json", "ndjson", "csv", "tsv", "plain"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            "csv" => Ok(Self::Csv),
+            "tsv" => Ok(Self::Tsv),
+            "plain" => Ok(Self::Plain),
+            _ => Err(format!("Unknown format: {}", s)),
+        }
+    }
+}
+
+/// Prints results of images one by one in the chosen format.
+///
+/// Every format carries the file path, and the category, name and score of each merged tag.
+/// JSON formats also include views, tiles, embeddings, timings and errors, the human-readable
+/// formats print views and tiles, and CSV and TSV keep to their four columns.
+pub struct Printer {
+    format: OutputFormat,
+    hyperlinks: bool,
+    views: bool,
+    paths: bool,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    file: Cow<'a, str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<Row<'a>>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    views: Option<&'a [View]>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tiles: Option<Vec<TileRecord<'a>>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embedding: Option<&'a [f32]>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timings: Option<&'a Timings>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TileRecord<'a> {
+    #[serde(flatten)]
+    bbox: BoundingBox,
+    tags: Vec<Row<'a>>,
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+    category: &'static str,
+    tag: &'a str,
+    score: f32,
+}
+
+impl Printer {
+    /// `views` prints the augmented views averaged over, and `paths` prints the path of each
+    /// image in human-readable formats.
+    pub fn new(format: OutputFormat, hyperlinks: bool, views: bool, paths: bool) -> Self {
+        Self {
+            format,
+            hyperlinks,
+            views,
+            paths,
+            count: 0,
+        }
+    }
+
+    pub fn begin(&self) {
+        match self.format {
+            OutputFormat::Json => println!("["),
+            OutputFormat::Csv => println!("file,category,tag,score"),
+            OutputFormat::Tsv => println!("file\tcategory\ttag\tscore"),
+            _ => (),
+        }
+    }
+
+    pub fn prediction(&mut self, path: &Path, prediction: &Prediction, tiles: &[TilePrediction]) {
+        match self.format {
+            OutputFormat::Table | OutputFormat::Plain => {
+                self.header(path);
+                if self.format == OutputFormat::Table {
+                    let display = format::Display {
+                        prediction,
+                        hyperlinks: self.hyperlinks,
+                    };
+                    println!("{}", display);
+                } else {
+                    print_plain(prediction);
+                }
+                if !tiles.is_empty() {
+                    println!();
+                    for tile in tiles {
+                        println!("{}", format::TileDisplay(tile));
+                    }
+                }
+                if self.views {
+                    println!("Views: {}", prediction.views().iter().join(", "));
+                }
+            }
+            OutputFormat::Json | OutputFormat::Ndjson => {
+                let record = Record {
+                    file: path.to_string_lossy(),
+                    tags: Some(rows(prediction)),
+                    views: Some(prediction.views()).filter(|_| self.views),
+                    tiles: Some(tiles).filter(|tiles| !tiles.is_empty()).map(|tiles| {
+                        tiles
+                            .iter()
+                            .map(|tile| TileRecord {
+                                bbox: tile.bbox,
+                                tags: rows(&tile.prediction),
+                            })
+                            .collect()
+                    }),
+                    embedding: prediction.embedding(),
+                    timings: prediction.timings(),
+                    error: None,
+                };
+                self.record(&record);
+            }
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                let path = path.to_string_lossy();
+                for row in rows(prediction) {
+                    if self.format == OutputFormat::Csv {
+                        println!(
+                            "{},{},{},{}",
+                            csv_field(&path),
+                            row.category,
+                            csv_field(row.tag),
+                            row.score
+                        );
+                    } else {
+                        println!(
+                            "{}\t{}\t{}\t{}",
+                            tsv_field(&path),
+                            row.category,
+                            tsv_field(row.tag),
+                            row.score
+                        );
+                    }
+                }
+            }
+        }
+        self.count += 1;
+    }
+
+    /// Records a failed image in JSON formats. Other formats leave errors to stderr.
+    pub fn error(&mut self, path: &Path, err: &anyhow::Error) {
+        if let OutputFormat::Json | OutputFormat::Ndjson = self.format {
+            let record = Record {
+                file: path.to_string_lossy(),
+                tags: None,
+                views: None,
+                tiles: None,
+                embedding: None,
+                timings: None,
+                error: Some(err.to_string()),
+            };
+            self.record(&record);
+            self.count += 1;
+        }
+    }
+
+    pub fn finish(&self) {
+        if self.format == OutputFormat::Json {
+            if self.count > 0 {
+                println!();
+            }
+            println!("]");
+        }
+    }
+
+    fn header(&self, path: &Path) {
+        if self.paths {
+            if self.count > 0 {
+                println!();
+            }
+            println!("{}:", path.display());
+        } else if self.count > 0 {
+            println!();
+        }
+    }
+
+    fn record(&self, record: &Record) {
+        let json = serde_json::to_string(record).expect("Failed to serialize record");
+        if self.format == OutputFormat::Json {
+            if self.count > 0 {
+                println!(",");
+            }
+            print!("  {}", json);
+        } else {
+            println!("{}", json);
+        }
+    }
+}
+
+fn print_plain(prediction: &Prediction) {
+    fn print_tags(tags: &[Tag]) {
+        for tag in tags {
+            println!("{} {:.3}", tag.name, tag.score);
+        }
+    }
+
+    println!("General tags");
+    print_tags(prediction.general());
+    println!();
+    println!("Characters");
+    print_tags(prediction.character());
+}
+
+fn rows<'a>(prediction: &'a Prediction) -> Vec<Row<'a>> {
+    let general = prediction.general().iter().map(|tag| Row {
+        category: "general",
+        tag: tag.name,
+        score: tag.score,
+    });
+    let character = prediction.character().iter().map(|tag| Row {
+        category: "character",
+        tag: tag.name,
+        score: tag.score,
+    });
+    general.chain(character).collect()
+}
+
+fn csv_field(s: &str) -> Cow<str> {
+    if s.contains(&[',', '"', '\n', '\r'][..]) {
+        Cow::Owned(format!("\"{}\"", s.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+// TSV has no quoting, so tabs and line breaks are escaped with backslashes, as are backslashes
+fn tsv_field(s: &str) -> Cow<str> {
+    if s.contains(&['\t', '\n', '\r', '\\'][..]) {
+        Cow::Owned(
+            s.replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r"),
+        )
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_fields() {
+        assert_eq!(csv_field("1girl"), "1girl");
+        assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+        assert_eq!(tsv_field("dir/img.png"), "dir/img.png");
+        assert_eq!(tsv_field("a\tb\nc\r\\d"), "a\\tb\\nc\\r\\\\d");
+    }
+}
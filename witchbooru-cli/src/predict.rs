@@ -0,0 +1,191 @@
+use crate::{
+    files::{self, InputOpt},
+    heatmap,
+    output::{OutputFormat, Printer},
+    ModelOpt,
+};
+
+use witchbooru::{image::ImageFormat, Augmentation, Classifier, Occlusion, SaliencyTarget, Tiling};
+
+use anyhow::anyhow;
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct Opt {
+    #[structopt(flatten)]
+    input: InputOpt,
+
+    #[structopt(flatten)]
+    model: ModelOpt,
+
+    #[structopt(short = "k", long, default_value = "20")]
+    topk: usize,
+
+    /// Average predictions over the original and horizontally flipped image
+    #[structopt(long)]
+    tta: bool,
+
+    /// Also average over five crops of the given size relative to the image
+    #[structopt(long, requires = "tta", validator = validate_tta_crop)]
+    tta_crop: Option<f32>,
+
+    /// Split the image into overlapping tiles of the given size and classify each
+    #[structopt(long, validator = validate_tile_size)]
+    tile_size: Option<u32>,
+
+    /// Fraction of overlap between adjacent tiles, at most 0.9
+    #[structopt(long, default_value = "0.25", validator = validate_tile_overlap)]
+    tile_overlap: f32,
+
+    /// Write an occlusion heatmap of the given general tag or character
+    #[structopt(long, requires = "saliency-output")]
+    saliency: Option<String>,
+
+    /// Path of the PNG file the heatmap is written to
+    #[structopt(long)]
+    saliency_output: Option<PathBuf>,
+
+    /// Number of rows and columns of occluded patches
+    #[structopt(long, default_value = "8")]
+    saliency_grid: u32,
+
+    /// Print durations of the stages of the prediction to stderr
+    #[structopt(long)]
+    timings: bool,
+
+    /// Output format, which defaults to table on a terminal and plain otherwise
+    #[structopt(long, possible_values = OutputFormat::VARIANTS)]
+    format: Option<OutputFormat>,
+}
+
+pub fn run(opt: Opt) -> anyhow::Result<()> {
+    let paths = opt.input.collect_images()?;
+    if opt.saliency.is_some() && paths.len() > 1 {
+        return Err(anyhow!("--saliency requires a single image"));
+    }
+
+    let augmentation = if opt.tta {
+        Some(Augmentation {
+            flip: true,
+            crop_ratio: opt.tta_crop,
+        })
+    } else {
+        None
+    };
+    let classifier = crate::load_classifier(&opt.model, opt.topk, augmentation)?;
+    let classifier = if opt.timings {
+        classifier.with_timings()
+    } else {
+        classifier
+    };
+
+    // decorations and hyperlinks only make sense on a terminal
+    let is_terminal = atty::is(atty::Stream::Stdout);
+    let format = opt.format.unwrap_or(if is_terminal {
+        OutputFormat::Table
+    } else {
+        OutputFormat::Plain
+    });
+    let mut printer = Printer::new(format, is_terminal, opt.tta, paths.len() > 1);
+
+    printer.begin();
+    let mut num_failed = 0;
+    for path in &paths {
+        if let Err(err) = predict(&classifier, &opt, path, &mut printer) {
+            eprintln!("Error: {}: {}", path.display(), err);
+            printer.error(path, &err);
+            num_failed += 1;
+        }
+    }
+    printer.finish();
+
+    if num_failed > 0 {
+        return Err(anyhow!(
+            "Failed to tag {} of {} images",
+            num_failed,
+            paths.len()
+        ));
+    }
+    Ok(())
+}
+
+fn predict(
+    classifier: &Classifier,
+    opt: &Opt,
+    path: &Path,
+    printer: &mut Printer,
+) -> anyhow::Result<()> {
+    let source = files::read_image(path)?;
+    let start = Instant::now();
+    let img = source.decode()?;
+    let decode_time = start.elapsed();
+
+    if let (Some(tag), Some(output)) = (&opt.saliency, &opt.saliency_output) {
+        let target = if classifier.character_tags().contains(tag) {
+            SaliencyTarget::Character(tag)
+        } else {
+            SaliencyTarget::General(tag)
+        };
+        let occlusion = Occlusion {
+            num_rows: opt.saliency_grid,
+            num_cols: opt.saliency_grid,
+        };
+        let saliency = classifier.saliency(img.clone(), target, &occlusion)?;
+        heatmap::overlay(&img, saliency.view()).save_with_format(output, ImageFormat::Png)?;
+    }
+
+    if let Some(tile_size) = opt.tile_size {
+        let tiling = Tiling {
+            tile_size,
+            overlap: opt.tile_overlap,
+        };
+        let mut prediction = classifier.predict_tiled(img, &tiling)?;
+        if let Some(timings) = prediction.merged.timings_mut() {
+            timings.decode = decode_time;
+            eprintln!("Timings: {}", timings);
+        }
+
+        printer.prediction(path, &prediction.merged, &prediction.tiles);
+    } else {
+        let mut prediction = classifier.predict(img)?;
+        if let Some(timings) = prediction.timings_mut() {
+            timings.decode = decode_time;
+            eprintln!("Timings: {}", timings);
+        }
+
+        printer.prediction(path, &prediction, &[]);
+    }
+
+    Ok(())
+}
+
+fn validate_tta_crop(s: String) -> Result<(), String> {
+    match s.parse::<f32>() {
+        Ok(ratio) if Augmentation::is_valid_crop_ratio(ratio) => Ok(()),
+        Ok(_) => Err("Crop ratio must be greater than 0 and at most 1".to_owned()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn validate_tile_size(s: String) -> Result<(), String> {
+    match s.parse::<u32>() {
+        Ok(0) => Err("Tile size must be positive".to_owned()),
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn validate_tile_overlap(s: String) -> Result<(), String> {
+    match s.parse::<f32>() {
+        Ok(overlap) if (0. ..=Tiling::MAX_OVERLAP).contains(&overlap) => Ok(()),
+        Ok(_) => Err(format!(
+            "Tile overlap must be between 0 and {}",
+            Tiling::MAX_OVERLAP
+        )),
+        Err(err) => Err(err.to_string()),
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{files, ModelOpt};
+
+use witchbooru::{image, SimilarityIndex};
+
+use std::{fs::File, io::BufReader, path::PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct IndexOpt {
+    /// Directory containing images
+    dir: PathBuf,
+
+    #[structopt(flatten)]
+    model: ModelOpt,
+
+    /// Path of the index file, which is updated if it already exists
+    #[structopt(short, long)]
+    index: PathBuf,
+}
+
+#[derive(StructOpt)]
+pub struct SearchOpt {
+    /// Image file, HTTP(S) URL, or `-` to read from stdin
+    image: PathBuf,
+
+    #[structopt(flatten)]
+    model: ModelOpt,
+
+    #[structopt(short, long)]
+    index: PathBuf,
+
+    #[structopt(short = "k", long, default_value = "10")]
+    topk: usize,
+}
+
+pub fn index(opt: IndexOpt) -> anyhow::Result<()> {
+    let classifier = crate::load_classifier(&opt.model, 0, None)?;
+
+    let mut index = if opt.index.exists() {
+        SimilarityIndex::read(BufReader::new(File::open(&opt.index)?))?
+    } else {
+        SimilarityIndex::new(classifier.general_tags().len())
+    };
+
+    let mut paths = Vec::new();
+    files::list_images(&opt.dir, true, &[], &mut paths)?;
+    for path in paths {
+        let img = match image::open(&path) {
+            Ok(img) => img,
+            Err(err) => {
+                eprintln!("Skipping {}: {}", path.display(), err);
+                continue;
+            }
+        };
+        let probs = match classifier.general_tag_probs(img) {
+            Ok(probs) => probs,
+            Err(err) => {
+                eprintln!("Skipping {}: {}", path.display(), err);
+                continue;
+            }
+        };
+        index.insert(path.to_string_lossy().into_owned(), &probs)?;
+    }
+
+    // the existing index is only replaced once the new one is completely written
+    files::write_atomically(&opt.index, |writer| Ok(index.write(writer)?))?;
+    eprintln!("Indexed {} images", index.len());
+
+    Ok(())
+}
+
+pub fn search(opt: SearchOpt) -> anyhow::Result<()> {
+    let classifier = crate::load_classifier(&opt.model, 0, None)?;
+    let index = SimilarityIndex::read(BufReader::new(File::open(&opt.index)?))?;
+
+    let img = files::open_image(&opt.image)?;
+    let probs = classifier.general_tag_probs(img)?;
+    for neighbor in index.search(&probs, opt.topk)? {
+        println!("{:.4} {}", neighbor.distance, neighbor.key);
+    }
+
+    Ok(())
+}
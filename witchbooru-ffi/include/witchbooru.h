@@ -0,0 +1,100 @@
+#ifndef WITCHBOORU_H
+#define WITCHBOORU_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Classifier of general tags and characters. A classifier may be used from multiple threads. */
+typedef struct WbClassifier WbClassifier;
+
+/* Top tags of an image, which stay valid after the classifier is freed. */
+typedef struct WbPrediction WbPrediction;
+
+/*
+ * Values of the enums are passed as uint32_t, so that out-of-range values are rejected instead
+ * of being undefined behavior.
+ */
+typedef enum WbPixelFormat {
+    WB_PIXEL_FORMAT_RGB = 0,
+    WB_PIXEL_FORMAT_RGBA = 1,
+    WB_PIXEL_FORMAT_BGRA = 2,
+} WbPixelFormat;
+
+typedef enum WbCategory {
+    WB_CATEGORY_GENERAL = 0,
+    WB_CATEGORY_CHARACTER = 1,
+} WbCategory;
+
+/*
+ * Functions returning a pointer return NULL on failure, after which wb_last_error() describes
+ * the error. Pointer arguments must not be NULL unless stated otherwise.
+ *
+ * Panics are also reported as errors if the library is built with the release-ffi profile,
+ * i.e. `cargo build --profile release-ffi -p witchbooru-ffi`. The release profile aborts the
+ * process on panics instead.
+ */
+
+/*
+ * Loads the model files in the directory `model_dir` (UTF-8) in the same way as the CLI,
+ * keeping the `topk` highest scoring tags of each category.
+ */
+WbClassifier *wb_classifier_open(const char *model_dir, size_t topk);
+
+/*
+ * Loads the model from the contents of its files.
+ *
+ * `neural_net` is either neural-net.onnx or neural-net.nnef.tar, and `naive_bayes` is either
+ * naive-bayes.npz or naive-bayes.q8.bin. `general_tags` and `character_tags` are the
+ * newline-separated tag lists. `calibration` may be NULL.
+ */
+WbClassifier *wb_classifier_from_memory(const uint8_t *neural_net, size_t neural_net_len,
+                                        const uint8_t *naive_bayes, size_t naive_bayes_len,
+                                        const char *general_tags, const char *character_tags,
+                                        const uint8_t *calibration, size_t calibration_len,
+                                        size_t topk);
+
+/* Frees the classifier. NULL is ignored. */
+void wb_classifier_free(WbClassifier *classifier);
+
+/* Predicts tags of an encoded image such as PNG or JPEG. */
+WbPrediction *wb_classifier_predict(const WbClassifier *classifier, const uint8_t *data,
+                                    size_t len);
+
+/*
+ * Predicts tags of `width * height` pixels without padding between rows. `format` is a
+ * WbPixelFormat. Fails if the size of the pixels does not fit in size_t.
+ */
+WbPrediction *wb_classifier_predict_rgb(const WbClassifier *classifier, const uint8_t *pixels,
+                                        uint32_t width, uint32_t height, uint32_t format);
+
+/* Frees the prediction. NULL is ignored. */
+void wb_prediction_free(WbPrediction *prediction);
+
+/*
+ * Number of tags of the category, which is a WbCategory, in descending order of score.
+ * Unknown categories have no tags.
+ */
+size_t wb_prediction_len(const WbPrediction *prediction, uint32_t category);
+
+/* Name of the `index`-th tag, or NULL if out of range. Owned by the prediction. */
+const char *wb_prediction_name(const WbPrediction *prediction, uint32_t category,
+                               size_t index);
+
+/* Score of the `index`-th tag, or NaN if out of range. */
+float wb_prediction_score(const WbPrediction *prediction, uint32_t category, size_t index);
+
+/*
+ * Message of the last error on the calling thread, or NULL if the last function returning a
+ * pointer succeeded. Valid until the next call on the same thread.
+ */
+const char *wb_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,258 @@
+//! C ABI of the classifier. See `include/witchbooru.h` for the documentation of each function,
+//! including the requirements on pointers.
+#![allow(clippy::missing_safety_doc)]
+
+use witchbooru::{
+    image,
+    models::{Calibration, NaiveBayes, NeuralNet},
+    Classifier, Params, PixelFormat, Prediction, Tag,
+};
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    io::Cursor,
+    os::raw::c_char,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+/// Opaque handle of a [`Classifier`].
+pub struct WbClassifier(Classifier);
+
+/// Opaque handle of a prediction, which owns its tag names so that it outlives the classifier.
+pub struct WbPrediction {
+    general: Vec<(CString, f32)>,
+    character: Vec<(CString, f32)>,
+}
+
+// values of `WbPixelFormat` and `WbCategory`, passed as integers because a C caller may pass
+// any value, and an out-of-range value of a Rust enum is undefined behavior
+const WB_PIXEL_FORMAT_RGB: u32 = 0;
+const WB_PIXEL_FORMAT_RGBA: u32 = 1;
+const WB_PIXEL_FORMAT_BGRA: u32 = 2;
+const WB_CATEGORY_GENERAL: u32 = 0;
+const WB_CATEGORY_CHARACTER: u32 = 1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wb_classifier_open(
+    model_dir: *const c_char,
+    topk: usize,
+) -> *mut WbClassifier {
+    guard(|| {
+        let model_dir = CStr::from_ptr(model_dir)
+            .to_str()
+            .map_err(|err| err.to_string())?;
+        Classifier::from_dir(model_dir, topk)
+            .map(WbClassifier)
+            .map_err(|err| err.to_string())
+    })
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn wb_classifier_from_memory(
+    neural_net: *const u8,
+    neural_net_len: usize,
+    naive_bayes: *const u8,
+    naive_bayes_len: usize,
+    general_tags: *const c_char,
+    character_tags: *const c_char,
+    calibration: *const u8,
+    calibration_len: usize,
+    topk: usize,
+) -> *mut WbClassifier {
+    guard(|| {
+        let neural_net = NeuralNet::from_bytes(bytes(neural_net, neural_net_len))
+            .map_err(|err| err.to_string())?;
+        let naive_bayes = NaiveBayes::from_bytes(bytes(naive_bayes, naive_bayes_len).to_vec())
+            .map_err(|err| err.to_string())?;
+
+        let calibration = if calibration.is_null() {
+            None
+        } else {
+            let calibration = bytes(calibration, calibration_len);
+            Some(Calibration::new(Cursor::new(calibration)).map_err(|err| err.to_string())?)
+        };
+
+        let params = Params {
+            neural_net,
+            naive_bayes,
+            calibration,
+            general_tags: lines(general_tags)?,
+            character_tags: lines(character_tags)?,
+            topk,
+            augmentation: None,
+        };
+        Classifier::new(params)
+            .map(WbClassifier)
+            .map_err(|err| err.to_string())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wb_classifier_free(classifier: *mut WbClassifier) {
+    if !classifier.is_null() {
+        drop(Box::from_raw(classifier));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wb_classifier_predict(
+    classifier: *const WbClassifier,
+    data: *const u8,
+    len: usize,
+) -> *mut WbPrediction {
+    let classifier = &(*classifier).0;
+    guard(|| {
+        let img = image::load_from_memory(bytes(data, len)).map_err(|err| err.to_string())?;
+        let prediction = classifier.predict(img).map_err(|err| err.to_string())?;
+        Ok(WbPrediction::new(&prediction))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wb_classifier_predict_rgb(
+    classifier: *const WbClassifier,
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    format: u32,
+) -> *mut WbPrediction {
+    let classifier = &(*classifier).0;
+    guard(|| {
+        let format = match format {
+            WB_PIXEL_FORMAT_RGB => PixelFormat::Rgb,
+            WB_PIXEL_FORMAT_RGBA => PixelFormat::Rgba,
+            WB_PIXEL_FORMAT_BGRA => PixelFormat::Bgra,
+            _ => return Err(format!("Unknown pixel format: {}", format)),
+        };
+        // a wrapped length would pass the size check of the buffer and read out of bounds
+        let len = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|len| len.checked_mul(format.bytes_per_pixel()))
+            .ok_or_else(|| format!("Image is too large: {}x{}", width, height))?;
+        let prediction = classifier
+            .predict_rgb(bytes(pixels, len), width, height, format)
+            .map_err(|err| err.to_string())?;
+        Ok(WbPrediction::new(&prediction))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wb_prediction_free(prediction: *mut WbPrediction) {
+    if !prediction.is_null() {
+        drop(Box::from_raw(prediction));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wb_prediction_len(
+    prediction: *const WbPrediction,
+    category: u32,
+) -> usize {
+    (*prediction).tags(category).len()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wb_prediction_name(
+    prediction: *const WbPrediction,
+    category: u32,
+    index: usize,
+) -> *const c_char {
+    match (*prediction).tags(category).get(index) {
+        Some((name, _)) => name.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wb_prediction_score(
+    prediction: *const WbPrediction,
+    category: u32,
+    index: usize,
+) -> f32 {
+    match (*prediction).tags(category).get(index) {
+        Some((_, score)) => *score,
+        None => f32::NAN,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wb_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+        Some(err) => err.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+impl WbPrediction {
+    fn new(prediction: &Prediction) -> Self {
+        fn to_owned(tags: &[Tag]) -> Vec<(CString, f32)> {
+            tags.iter()
+                .map(|tag| (CString::new(tag.name).unwrap_or_default(), tag.score))
+                .collect()
+        }
+
+        Self {
+            general: to_owned(prediction.general()),
+            character: to_owned(prediction.character()),
+        }
+    }
+
+    /// Tags of the category, which are none if the category is unknown.
+    fn tags(&self, category: u32) -> &[(CString, f32)] {
+        match category {
+            WB_CATEGORY_GENERAL => &self.general,
+            WB_CATEGORY_CHARACTER => &self.character,
+            _ => &[],
+        }
+    }
+}
+
+/// Runs `f` and returns its result as an owned pointer, or null after storing the error
+/// for [`wb_last_error`]. Panics are caught since unwinding into C is undefined behavior,
+/// which only works when built with `panic = "unwind"` as in the `release-ffi` profile.
+fn guard<T, F>(f: F) -> *mut T
+where
+    F: FnOnce() -> std::result::Result<T, String>,
+{
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|err| {
+        let message = err
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| err.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(format!("panicked: {}", message))
+    });
+
+    LAST_ERROR.with(|last_error| match result {
+        Ok(value) => {
+            *last_error.borrow_mut() = None;
+            Box::into_raw(Box::new(value))
+        }
+        Err(err) => {
+            *last_error.borrow_mut() = Some(CString::new(err.replace('\0', "")).unwrap());
+            ptr::null_mut()
+        }
+    })
+}
+
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+unsafe fn lines(text: *const c_char) -> std::result::Result<Vec<String>, String> {
+    let text = CStr::from_ptr(text)
+        .to_str()
+        .map_err(|err| err.to_string())?;
+    Ok(text.lines().map(ToOwned::to_owned).collect())
+}
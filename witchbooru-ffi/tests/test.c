@@ -0,0 +1,154 @@
+/*
+ * Exercises the C API with a model directory and an image:
+ *
+ *     make test MODEL=../model IMAGE=path/to/image.jpg
+ */
+#include <assert.h>
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "witchbooru.h"
+
+static uint8_t *read_file(const char *path, size_t *len) {
+    FILE *file = fopen(path, "rb");
+    if (!file) {
+        perror(path);
+        exit(1);
+    }
+    fseek(file, 0, SEEK_END);
+    *len = (size_t)ftell(file);
+    fseek(file, 0, SEEK_SET);
+
+    uint8_t *data = malloc(*len + 1);
+    if (fread(data, 1, *len, file) != *len) {
+        perror(path);
+        exit(1);
+    }
+    data[*len] = 0;
+    fclose(file);
+    return data;
+}
+
+static char *join(const char *dir, const char *name) {
+    char *path = malloc(strlen(dir) + strlen(name) + 2);
+    sprintf(path, "%s/%s", dir, name);
+    return path;
+}
+
+static void print_prediction(const WbPrediction *prediction) {
+    const WbCategory categories[] = {WB_CATEGORY_GENERAL, WB_CATEGORY_CHARACTER};
+    for (int i = 0; i < 2; i++) {
+        size_t len = wb_prediction_len(prediction, categories[i]);
+        for (size_t j = 0; j < len; j++) {
+            const char *name = wb_prediction_name(prediction, categories[i], j);
+            float score = wb_prediction_score(prediction, categories[i], j);
+            assert(name);
+            assert(!isnan(score));
+            if (j > 0) {
+                assert(score <= wb_prediction_score(prediction, categories[i], j - 1));
+            }
+            printf("%s %s: %.3f\n", i == 0 ? "general" : "character", name, score);
+        }
+        assert(!wb_prediction_name(prediction, categories[i], len));
+        assert(isnan(wb_prediction_score(prediction, categories[i], len)));
+    }
+
+    /* unknown categories have no tags */
+    assert(wb_prediction_len(prediction, 2) == 0);
+    assert(!wb_prediction_name(prediction, 2, 0));
+    assert(isnan(wb_prediction_score(prediction, 2, 0)));
+}
+
+int main(int argc, char **argv) {
+    if (argc != 3) {
+        fprintf(stderr, "usage: %s MODEL_DIR IMAGE\n", argv[0]);
+        return 2;
+    }
+    const char *model_dir = argv[1];
+
+    /* errors are reported through wb_last_error */
+    assert(!wb_classifier_open("/nonexistent", 10));
+    assert(wb_last_error() && strlen(wb_last_error()) > 0);
+    printf("expected error: %s\n", wb_last_error());
+
+    WbClassifier *classifier = wb_classifier_open(model_dir, 10);
+    if (!classifier) {
+        fprintf(stderr, "failed to load model: %s\n", wb_last_error());
+        return 1;
+    }
+    assert(!wb_last_error());
+
+    /* encoded bytes */
+    size_t len;
+    uint8_t *data = read_file(argv[2], &len);
+    WbPrediction *prediction = wb_classifier_predict(classifier, data, len);
+    assert(prediction);
+    assert(wb_prediction_len(prediction, WB_CATEGORY_GENERAL) <= 10);
+    print_prediction(prediction);
+
+    /* a prediction outlives its classifier */
+    wb_classifier_free(classifier);
+    print_prediction(prediction);
+    wb_prediction_free(prediction);
+
+    /* load from memory */
+    char *path = join(model_dir, "neural-net.onnx");
+    size_t neural_net_len;
+    uint8_t *neural_net = read_file(path, &neural_net_len);
+    free(path);
+    path = join(model_dir, "naive-bayes.npz");
+    size_t naive_bayes_len;
+    uint8_t *naive_bayes = read_file(path, &naive_bayes_len);
+    free(path);
+    path = join(model_dir, "general-tags.txt");
+    size_t tags_len;
+    char *general_tags = (char *)read_file(path, &tags_len);
+    free(path);
+    path = join(model_dir, "character-tags.txt");
+    char *character_tags = (char *)read_file(path, &tags_len);
+    free(path);
+
+    classifier = wb_classifier_from_memory(neural_net, neural_net_len, naive_bayes,
+                                           naive_bayes_len, general_tags, character_tags, NULL,
+                                           0, 5);
+    if (!classifier) {
+        fprintf(stderr, "failed to load model from memory: %s\n", wb_last_error());
+        return 1;
+    }
+    free(neural_net);
+    free(naive_bayes);
+    free(general_tags);
+    free(character_tags);
+
+    /* invalid image */
+    const uint8_t garbage[] = {1, 2, 3, 4};
+    assert(!wb_classifier_predict(classifier, garbage, sizeof(garbage)));
+    printf("expected error: %s\n", wb_last_error());
+
+    /* raw pixels */
+    const uint32_t width = 64, height = 48;
+    uint8_t *pixels = malloc(width * height * 4);
+    for (uint32_t i = 0; i < width * height * 4; i++) {
+        pixels[i] = (uint8_t)(i * 7);
+    }
+    prediction = wb_classifier_predict_rgb(classifier, pixels, width, height,
+                                           WB_PIXEL_FORMAT_BGRA);
+    assert(prediction);
+    assert(wb_prediction_len(prediction, WB_CATEGORY_GENERAL) <= 5);
+    print_prediction(prediction);
+    wb_prediction_free(prediction);
+
+    /* unknown pixel format */
+    assert(!wb_classifier_predict_rgb(classifier, pixels, width, height, 3));
+    printf("expected error: %s\n", wb_last_error());
+    free(pixels);
+
+    wb_classifier_free(classifier);
+    wb_classifier_free(NULL);
+    wb_prediction_free(NULL);
+
+    printf("ok\n");
+    return 0;
+}
@@ -1,81 +1,126 @@
 mod request;
 mod resource;
 
-use witchbooru::Classifier;
-
 use netlify_lambda_http::{
     http::{self, HeaderValue},
     lambda, Request, Response,
 };
 use serde_json::{json, Value};
+use std::time::{Duration, Instant};
 use tokio::sync::OnceCell;
+use tracing::Instrument;
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
 
 type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
 
-static CLASSIFIER: OnceCell<Classifier> = OnceCell::const_new();
+static MODEL: OnceCell<resource::Model> = OnceCell::const_new();
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    tracing_subscriber::fmt()
+        .json()
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
 
-    let classifier = async {
-        CLASSIFIER
-            .get_or_try_init(resource::create_classifier)
+    let model = async {
+        MODEL
+            .get_or_try_init(resource::load_model)
             .await
             .map_err(Into::into)
     };
 
-    let runtime = lambda::run(netlify_lambda_http::handler(|req, _| async {
-        let mut response = match handler(req).await {
-            Ok(mut value) => {
-                let obj = value
-                    .as_object_mut()
-                    .expect("Tried to return non-object JSON value");
-                obj.insert("ok".to_string(), Value::Bool(true));
-
-                Response::new(value.to_string())
-            }
-            Err(err) => {
-                let value = json!({
-                    "ok": false,
-                    "error": err.to_string()
-                });
-                Response::builder()
-                    .status(http::StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(value.to_string())?
-            }
-        };
-
-        let headers = response.headers_mut();
-        headers.insert(
-            http::header::CONTENT_TYPE,
-            HeaderValue::from_static("application/json"),
-        );
-        headers.insert(
-            http::header::ACCESS_CONTROL_ALLOW_ORIGIN,
-            HeaderValue::from_static("*"),
-        );
+    let runtime = lambda::run(netlify_lambda_http::handler(|req, ctx: lambda::Context| {
+        let span = tracing::info_span!("request", request_id = %ctx.request_id);
+        async {
+            let mut response = match handler(req).await {
+                Ok(mut value) => {
+                    let obj = value
+                        .as_object_mut()
+                        .expect("Tried to return non-object JSON value");
+                    obj.insert("ok".to_string(), Value::Bool(true));
+
+                    Response::new(value.to_string())
+                }
+                Err(err) => {
+                    let value = json!({
+                        "ok": false,
+                        "error": err.to_string()
+                    });
+                    Response::builder()
+                        .status(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(value.to_string())?
+                }
+            };
+
+            let headers = response.headers_mut();
+            headers.insert(
+                http::header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            headers.insert(
+                http::header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                HeaderValue::from_static("*"),
+            );
 
-        Ok(response)
+            Ok(response)
+        }
+        .instrument(span)
     }));
 
-    let _ = futures::try_join!(classifier, runtime)?;
+    let _ = futures::try_join!(model, runtime)?;
     Ok(())
 }
 
 async fn handler(req: Request) -> anyhow::Result<Value> {
-    let classifier = CLASSIFIER.get_or_try_init(resource::create_classifier);
+    let model = MODEL.get_or_try_init(resource::load_model);
     let img = request::extract_image(&req);
 
-    let (classifier, img) = futures::try_join!(classifier, img)?;
-    let prediction = tokio::task::spawn_blocking(move || classifier.predict(img)).await??;
-    log::info!("Finished inference");
+    let (model, (img, decode_time)) = futures::try_join!(model, img)?;
+    let start = Instant::now();
+    let mut prediction = model.classifier.predict(img).await?;
+    tracing::info!(
+        path = req.uri().path(),
+        elapsed_ms = millis(start.elapsed()),
+        model_version = %model.version,
+        "Finished inference"
+    );
+
+    if let Some(timings) = prediction.timings_mut() {
+        timings.decode = decode_time;
+        tracing::info!(
+            decode_ms = millis(timings.decode),
+            preprocess_ms = millis(timings.preprocess),
+            network_ms = millis(timings.network),
+            head_ms = millis(timings.head),
+            width = timings.width,
+            height = timings.height,
+            cache_hit = timings.cache_hit,
+            "Timings"
+        );
+    }
 
     let general: Vec<_> = prediction.general().iter().collect();
     let character: Vec<_> = prediction.character().iter().collect();
 
-    Ok(json!({
+    let mut value = json!({
         "general": general,
         "character": character,
-    }))
+    });
+    if let Some(embedding) = prediction.embedding() {
+        value["embedding"] = json!(embedding);
+    }
+    if let Some(timings) = prediction.timings() {
+        value["timings"] = json!(timings);
+    }
+
+    Ok(value)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.
 }
@@ -7,30 +7,33 @@ use netlify_lambda_http::{http, Request, RequestExt};
 use serde::Deserialize;
 use std::{
     io::{Cursor, Read},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-pub async fn extract_image(req: &Request) -> anyhow::Result<DynamicImage> {
+/// Image along with the time it took to decode it.
+pub type Decoded = (DynamicImage, Duration);
+
+pub async fn extract_image(req: &Request) -> anyhow::Result<Decoded> {
     if req.method() == http::Method::POST {
         if let Some(img) = from_multipart(req).await? {
-            log::info!("Extracted image from multipart/form-data");
+            tracing::info!("Extracted image from multipart/form-data");
             return Ok(img);
         }
         if let Some(img) = from_form_urlencoded(req).await? {
-            log::info!("Extracted image from application/x-www-form-urlencoded");
+            tracing::info!("Extracted image from application/x-www-form-urlencoded");
             return Ok(img);
         }
     }
 
     if let Some(img) = from_query_params(req).await? {
-        log::info!("Extracted image from query parameters");
+        tracing::info!("Extracted image from query parameters");
         return Ok(img);
     }
 
     Err(anyhow!("Missing file or url"))
 }
 
-async fn from_multipart(req: &Request) -> anyhow::Result<Option<DynamicImage>> {
+async fn from_multipart(req: &Request) -> anyhow::Result<Option<Decoded>> {
     let content_type = if let Some(content_type) = req.headers().get(http::header::CONTENT_TYPE) {
         content_type.to_str()?
     } else {
@@ -59,9 +62,7 @@ async fn from_multipart(req: &Request) -> anyhow::Result<Option<DynamicImage>> {
                 field.data.read_to_end(&mut buf)?;
 
                 if !buf.is_empty() {
-                    let img = tokio::task::spawn_blocking(move || image::load_from_memory(&buf))
-                        .await??;
-                    return Ok(Some(img));
+                    return decode(buf).await.map(Some);
                 }
             }
             _ => (),
@@ -75,7 +76,7 @@ async fn from_multipart(req: &Request) -> anyhow::Result<Option<DynamicImage>> {
     }
 }
 
-async fn from_form_urlencoded(req: &Request) -> anyhow::Result<Option<DynamicImage>> {
+async fn from_form_urlencoded(req: &Request) -> anyhow::Result<Option<Decoded>> {
     #[derive(Deserialize)]
     struct RequestParams {
         url: String,
@@ -88,7 +89,7 @@ async fn from_form_urlencoded(req: &Request) -> anyhow::Result<Option<DynamicIma
     }
 }
 
-async fn from_query_params(req: &Request) -> anyhow::Result<Option<DynamicImage>> {
+async fn from_query_params(req: &Request) -> anyhow::Result<Option<Decoded>> {
     if let Some(url) = req.query_string_parameters().get("url") {
         download_image(url).await.map(Some)
     } else {
@@ -96,7 +97,7 @@ async fn from_query_params(req: &Request) -> anyhow::Result<Option<DynamicImage>
     }
 }
 
-async fn download_image(url: &str) -> anyhow::Result<DynamicImage> {
+async fn download_image(url: &str) -> anyhow::Result<Decoded> {
     const TIMEOUT_SECS: u64 = 5;
     const MAX_SIZE: u64 = 8 * 1024 * 1024; // 8 MiB
 
@@ -128,7 +129,14 @@ async fn download_image(url: &str) -> anyhow::Result<DynamicImage> {
         }
     }
 
-    tokio::task::spawn_blocking(move || image::load_from_memory(&buf))
-        .await?
-        .map_err(Into::into)
+    decode(buf).await
+}
+
+async fn decode(buf: Vec<u8>) -> anyhow::Result<Decoded> {
+    tokio::task::spawn_blocking(move || {
+        let start = Instant::now();
+        let img = image::load_from_memory(&buf)?;
+        Ok((img, start.elapsed()))
+    })
+    .await?
 }
@@ -1,16 +1,26 @@
 use witchbooru::{
-    models::{NaiveBayes, NeuralNet},
-    Classifier, Params,
+    cache::{self, Cache},
+    model_dir,
+    models::{Calibration, NaiveBayes, NeuralNet},
+    AsyncClassifier, Classifier, Params,
 };
 
 use anyhow::anyhow;
 use futures::future;
-use rusoto_core::{ByteStream, Region};
-use rusoto_s3::{GetObjectRequest, S3Client, S3};
-use std::io::Cursor;
+use rusoto_core::{ByteStream, Region, RusotoError};
+use rusoto_s3::{
+    GetObjectError, GetObjectRequest, HeadObjectError, HeadObjectRequest, S3Client, S3,
+};
+use std::{io::Cursor, time::SystemTime};
 use tokio::io::AsyncReadExt;
 
-pub async fn create_classifier() -> anyhow::Result<Classifier> {
+/// Classifier along with the version of the model components it was loaded from.
+pub struct Model {
+    pub classifier: AsyncClassifier,
+    pub version: String,
+}
+
+pub async fn load_model() -> anyhow::Result<Model> {
     let region_name = std::env::var("AWS_REGION").unwrap_or_else(|_| "ap-northeast-1".to_owned());
     let bucket = std::env::var("BUCKET_NAME")?;
     let region = if std::env::var("AWS_SAM_LOCAL").is_ok() {
@@ -22,59 +32,179 @@ pub async fn create_classifier() -> anyhow::Result<Classifier> {
         region_name.parse()?
     };
     let client = S3Client::new(region);
-    log::info!("Initialized S3 client");
+    tracing::info!("Initialized S3 client");
+
+    // the cache is disabled by default since nothing bounds its size in /tmp
+    let cache_dir = std::env::var("CACHE_DIR")
+        .ok()
+        .filter(|dir| !dir.is_empty());
 
-    let (neural_net, naive_bayes, general_tags, character_tags) = futures::try_join!(
+    let (
+        (neural_net, neural_net_fingerprint),
+        naive_bayes,
+        calibration,
+        general_tags,
+        character_tags,
+    ) = futures::try_join!(
         download_neural_net(&client, bucket.clone()),
         download_naive_bayes(&client, bucket.clone()),
-        download_tags(&client, bucket.clone(), "general-tags.txt".into()),
-        download_tags(&client, bucket, "character-tags.txt".into())
+        download_calibration(&client, bucket.clone()),
+        download_tags(&client, bucket.clone(), model_dir::GENERAL_TAGS.into()),
+        download_tags(&client, bucket, model_dir::CHARACTER_TAGS.into())
     )?;
-    log::info!("Loaded all model components");
+
+    let embedding_node = std::env::var("EMBEDDING_NODE").ok();
+    let version = cache::model_version(
+        &neural_net_fingerprint,
+        embedding_node.as_deref(),
+        &general_tags,
+    );
+    tracing::info!(model_version = %version, "Loaded all model components");
+
+    let cache = match cache_dir {
+        Some(dir) => {
+            let version = version.clone();
+            let cache = tokio::task::spawn_blocking(move || Cache::open(dir, &version)).await??;
+            tracing::info!("Opened cache");
+            Some(cache)
+        }
+        None => None,
+    };
 
     let params = Params {
         neural_net,
         naive_bayes,
+        calibration,
         general_tags,
         character_tags,
         topk: 20,
+        augmentation: None,
+    };
+
+    let classifier = Classifier::new(params)?.with_timings();
+    let classifier = match cache {
+        Some(cache) => classifier.with_cache(cache),
+        None => classifier,
     };
 
-    Classifier::new(params).map_err(Into::into)
+    // limit concurrent inferences so that images and activations fit in memory
+    let num_threads = match std::env::var("INFERENCE_THREADS") {
+        Ok(num_threads) => num_threads.parse()?,
+        Err(_) => 1,
+    };
+    Ok(Model {
+        classifier: AsyncClassifier::new(classifier, num_threads),
+        version,
+    })
 }
 
-async fn download_neural_net(client: &S3Client, bucket: String) -> anyhow::Result<NeuralNet> {
+async fn download_neural_net(
+    client: &S3Client,
+    bucket: String,
+) -> anyhow::Result<(NeuralNet, String)> {
     let num_chunks = std::env::var("NEURAL_NET_NUM_CHUNKS")
         .unwrap_or_else(|_| "1".to_owned())
         .parse()?;
 
-    let bin = if num_chunks == 1 {
-        download_binary(client, bucket, "neural-net.onnx".into()).await?
+    // prefer the compiled neural net unless it is missing or stale
+    let source = if num_chunks == 1 {
+        model_dir::NEURAL_NET.to_owned()
+    } else {
+        format!("{}.part0", model_dir::NEURAL_NET)
+    };
+    let is_compiled =
+        is_up_to_date(client, &bucket, model_dir::COMPILED_NEURAL_NET, &source).await?;
+
+    let embedding_node = std::env::var("EMBEDDING_NODE").ok();
+    if is_compiled && embedding_node.is_some() {
+        tracing::warn!(
+            "EMBEDDING_NODE is ignored since the embedding node of the compiled neural net \
+            is specified at compile time"
+        );
+    }
+
+    let bin = if is_compiled {
+        download_binary(client, bucket, model_dir::COMPILED_NEURAL_NET.into()).await?
+    } else if num_chunks == 1 {
+        download_binary(client, bucket, model_dir::NEURAL_NET.into()).await?
     } else {
         let chunks = future::try_join_all((0..num_chunks).map(|i| {
-            download_binary(client, bucket.clone(), format!("neural-net.onnx.part{}", i))
+            download_binary(
+                client,
+                bucket.clone(),
+                format!("{}.part{}", model_dir::NEURAL_NET, i),
+            )
         }))
         .await?;
         chunks.concat()
     };
     let reader = Cursor::new(bin);
-    log::info!("Downloaded neural net");
+    tracing::info!(
+        compiled = is_compiled,
+        size = reader.get_ref().len(),
+        "Downloaded neural net"
+    );
 
-    let neural_net = tokio::task::spawn_blocking(|| NeuralNet::new(reader)).await?;
-    log::info!("Loaded neural net");
+    let (neural_net, fingerprint) = tokio::task::spawn_blocking(move || {
+        let fingerprint = cache::fingerprint(reader.get_ref());
+        // embedding node of compiled neural net is specified at compile time
+        let neural_net = match embedding_node {
+            _ if is_compiled => NeuralNet::from_compiled(reader),
+            Some(name) => NeuralNet::with_embedding(reader, &name),
+            None => NeuralNet::new(reader),
+        };
+        (neural_net, fingerprint)
+    })
+    .await?;
+    tracing::info!("Loaded neural net");
 
-    neural_net.map_err(Into::into)
+    Ok((neural_net?, fingerprint))
 }
 
 async fn download_naive_bayes(client: &S3Client, bucket: String) -> anyhow::Result<NaiveBayes> {
-    let bin = download_binary(client, bucket, "naive-bayes.npz".into()).await?;
-    let reader = Cursor::new(bin);
-    log::info!("Downloaded naive bayes");
+    // prefer the quantized naive Bayes unless it is missing or stale
+    let is_quantized = is_up_to_date(
+        client,
+        &bucket,
+        model_dir::QUANTIZED_NAIVE_BAYES,
+        model_dir::NAIVE_BAYES,
+    )
+    .await?;
+    let key = if is_quantized {
+        model_dir::QUANTIZED_NAIVE_BAYES
+    } else {
+        model_dir::NAIVE_BAYES
+    };
+    let bin = download_binary(client, bucket, key.into()).await?;
+    tracing::info!(size = bin.len(), "Downloaded naive bayes");
+
+    let naive_bayes = tokio::task::spawn_blocking(|| NaiveBayes::from_bytes(bin)).await?;
+    tracing::info!("Loaded naive bayes");
+
+    let naive_bayes = naive_bayes?;
+    Ok(match std::env::var("SPARSE_CUTOFF") {
+        Ok(cutoff) => naive_bayes.with_sparse_cutoff(cutoff.parse()?),
+        Err(_) => naive_bayes,
+    })
+}
 
-    let naive_bayes = tokio::task::spawn_blocking(|| NaiveBayes::new(reader)).await?;
-    log::info!("Loaded naive bayes");
+async fn download_calibration(
+    client: &S3Client,
+    bucket: String,
+) -> anyhow::Result<Option<Calibration>> {
+    let calibration =
+        download_binary_if_exists(client, bucket, model_dir::CALIBRATION.into()).await?;
+    let bin = match calibration {
+        Some(bin) => bin,
+        None => {
+            tracing::info!("Calibration not found, using raw scores");
+            return Ok(None);
+        }
+    };
+    let reader = Cursor::new(bin);
+    tracing::info!("Downloaded calibration");
 
-    naive_bayes.map_err(Into::into)
+    Calibration::new(reader).map(Some).map_err(Into::into)
 }
 
 async fn download_tags(
@@ -93,6 +223,55 @@ async fn download_tags(
     Ok(tags)
 }
 
+/// Whether the object `derived` exists and is not older than `source`, which it was
+/// created from, so that a stale one does not shadow an updated model.
+async fn is_up_to_date(
+    client: &S3Client,
+    bucket: &str,
+    derived: &str,
+    source: &str,
+) -> anyhow::Result<bool> {
+    let (derived_time, source_time) = futures::try_join!(
+        last_modified(client, bucket.to_owned(), derived.to_owned()),
+        last_modified(client, bucket.to_owned(), source.to_owned())
+    )?;
+    Ok(match (derived_time, source_time) {
+        (None, _) => false,
+        (Some(derived_time), Some(source_time)) if derived_time < source_time => {
+            tracing::warn!("Ignoring {} since it is older than {}", derived, source);
+            false
+        }
+        _ => true,
+    })
+}
+
+/// Returns when the object was last modified, or `None` if it does not exist.
+async fn last_modified(
+    client: &S3Client,
+    bucket: String,
+    key: String,
+) -> anyhow::Result<Option<SystemTime>> {
+    let output = client
+        .head_object(HeadObjectRequest {
+            bucket,
+            key,
+            ..Default::default()
+        })
+        .await;
+    match output {
+        Ok(output) => {
+            let last_modified = output
+                .last_modified
+                .ok_or_else(|| anyhow!("Missing last modified time"))?;
+            Ok(Some(httpdate::parse_http_date(&last_modified)?))
+        }
+        Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => Ok(None),
+        // responses to HEAD have no body telling the error code
+        Err(RusotoError::Unknown(response)) if response.status == 404 => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
 async fn download_binary(
     client: &S3Client,
     bucket: String,
@@ -107,6 +286,25 @@ async fn download_binary(
     Ok(buf)
 }
 
+async fn download_binary_if_exists(
+    client: &S3Client,
+    bucket: String,
+    key: String,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    match download_binary(client, bucket, key).await {
+        Ok(bin) => Ok(Some(bin)),
+        Err(err) => {
+            if let Some(RusotoError::Service(GetObjectError::NoSuchKey(_))) =
+                err.downcast_ref::<RusotoError<GetObjectError>>()
+            {
+                Ok(None)
+            } else {
+                Err(err)
+            }
+        }
+    }
+}
+
 async fn get_byte_stream(
     client: &S3Client,
     bucket: String,
@@ -0,0 +1,223 @@
+use witchbooru::{
+    image::{self, DynamicImage, ImageBuffer},
+    model_dir,
+    models::NeuralNet,
+    Error, ModelDir, Params, PixelFormat, Prediction, Tag, TaggingPool,
+};
+
+use numpy::{IntoPyArray, PyArray1, PyReadonlyArray3};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+/// Image given as a file path, encoded bytes or an array of shape `(height, width, 3 or 4)`.
+#[derive(FromPyObject)]
+enum Image<'a> {
+    Path(PathBuf),
+    Bytes(&'a [u8]),
+    Array(PyReadonlyArray3<'a, u8>),
+}
+
+#[pyclass]
+struct Classifier(witchbooru::Classifier);
+
+#[pymethods]
+impl Classifier {
+    /// Loads the model files in the directory `model` in the same way as the CLI.
+    ///
+    /// With `embedding_node`, the ONNX model also outputs the tensor of that node, which is
+    /// returned as the embedding of predictions.
+    #[new]
+    #[args(topk = "20", sparse_cutoff = "None", embedding_node = "None")]
+    fn new(
+        py: Python,
+        model: PathBuf,
+        topk: usize,
+        sparse_cutoff: Option<f32>,
+        embedding_node: Option<String>,
+    ) -> PyResult<Self> {
+        py.allow_threads(|| load_classifier(&model, topk, sparse_cutoff, embedding_node.as_deref()))
+            .map(Self)
+            .map_err(to_py_err)
+    }
+
+    #[getter]
+    fn general_tags(&self) -> Vec<String> {
+        self.0.general_tags().to_vec()
+    }
+
+    #[getter]
+    fn character_tags(&self) -> Vec<String> {
+        self.0.character_tags().to_vec()
+    }
+
+    /// Returns `{"general": {tag: score}, "character": {tag: score}}` of the top tags,
+    /// along with `"embedding"` as a list of floats if the model outputs one.
+    fn predict(&self, py: Python, image: Image) -> PyResult<PyObject> {
+        let prediction = match &image {
+            Image::Array(array) => {
+                let (width, height, format) = array_format(array)?;
+                // Python code may write to the array once the GIL is released
+                let pixels = array.as_slice()?.to_vec();
+                py.allow_threads(|| self.0.predict_rgb(&pixels, width, height, format))
+            }
+            image => {
+                let img = decode(py, image)?;
+                py.allow_threads(|| self.0.predict(img))
+            }
+        }
+        .map_err(to_py_err)?;
+        to_dict(py, &prediction)
+    }
+
+    /// Predicts images at `paths` on multiple threads and returns the results in the same order.
+    ///
+    /// An image that fails has the exception instead of its prediction, so that one broken
+    /// file does not discard the predictions of the others.
+    #[args(num_threads = "None")]
+    fn predict_batch(
+        &self,
+        py: Python,
+        paths: Vec<PathBuf>,
+        num_threads: Option<usize>,
+    ) -> PyResult<Vec<PyObject>> {
+        let mut indices: HashMap<&Path, Vec<usize>> = HashMap::new();
+        for (i, path) in paths.iter().enumerate().rev() {
+            indices.entry(path).or_default().push(i);
+        }
+
+        let num_threads = num_threads.unwrap_or_else(num_cpus::get);
+        let results = py.allow_threads(|| {
+            let pool = TaggingPool::new(&self.0, num_threads);
+            pool.run(paths.clone(), |results| results.collect::<Vec<_>>())
+        });
+
+        let mut predictions = vec![None; paths.len()];
+        for (path, result) in results {
+            let prediction = match result {
+                Ok(prediction) => to_dict(py, &prediction)?,
+                Err(err) => to_py_err(err).instance(py).into(),
+            };
+            let i = indices.get_mut(&*path).and_then(Vec::pop).unwrap();
+            predictions[i] = Some(prediction);
+        }
+        Ok(predictions.into_iter().map(Option::unwrap).collect())
+    }
+
+    /// Returns probabilities of all general tags as a float32 array.
+    fn general_tag_probs(&self, py: Python, image: Image) -> PyResult<Py<PyArray1<f32>>> {
+        let img = decode(py, &image)?;
+        let probs = py
+            .allow_threads(|| self.0.general_tag_probs(img))
+            .map_err(to_py_err)?;
+        Ok(probs.into_pyarray(py).to_owned())
+    }
+}
+
+#[pymodule]
+fn witchbooru_py(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Classifier>()?;
+    Ok(())
+}
+
+fn load_classifier(
+    model: &Path,
+    topk: usize,
+    sparse_cutoff: Option<f32>,
+    embedding_node: Option<&str>,
+) -> witchbooru::Result<witchbooru::Classifier> {
+    let dir = ModelDir::new(model);
+    let neural_net = match embedding_node {
+        // the embedding node of the compiled neural net is fixed when it is compiled
+        Some(node) => {
+            let reader = BufReader::new(File::open(dir.join(model_dir::NEURAL_NET))?);
+            NeuralNet::with_embedding(reader, node)?
+        }
+        None => dir.neural_net()?,
+    };
+    let naive_bayes = dir.naive_bayes()?;
+    let naive_bayes = match sparse_cutoff {
+        Some(cutoff) => naive_bayes.with_sparse_cutoff(cutoff),
+        None => naive_bayes,
+    };
+
+    let params = Params {
+        neural_net,
+        naive_bayes,
+        calibration: dir.calibration()?,
+        general_tags: dir.general_tags()?,
+        character_tags: dir.character_tags()?,
+        topk,
+        augmentation: None,
+    };
+    witchbooru::Classifier::new(params)
+}
+
+fn decode(py: Python, input: &Image) -> PyResult<DynamicImage> {
+    match input {
+        Image::Path(path) => py
+            .allow_threads(|| image::open(path))
+            .map_err(|err| to_py_err(err.into())),
+        Image::Bytes(bytes) => py
+            .allow_threads(|| image::load_from_memory(bytes))
+            .map_err(|err| to_py_err(err.into())),
+        Image::Array(array) => {
+            let (width, height, format) = array_format(array)?;
+            let pixels = array.as_slice()?.to_vec();
+            let img = match format {
+                PixelFormat::Rgb => {
+                    ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+                }
+                _ => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8),
+            };
+            img.ok_or_else(|| to_py_err(Error::PixelBufferSize))
+        }
+    }
+}
+
+fn array_format(array: &PyReadonlyArray3<u8>) -> PyResult<(u32, u32, PixelFormat)> {
+    let (height, width, channels) = match *array.shape() {
+        [height, width, channels] => (height as u32, width as u32, channels),
+        _ => unreachable!(),
+    };
+    let format = match channels {
+        3 => PixelFormat::Rgb,
+        4 => PixelFormat::Rgba,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "expected 3 or 4 channels, got {}",
+                channels
+            )))
+        }
+    };
+    Ok((width, height, format))
+}
+
+fn to_dict(py: Python, prediction: &Prediction) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("general", tags_to_dict(py, prediction.general())?)?;
+    dict.set_item("character", tags_to_dict(py, prediction.character())?)?;
+    if let Some(embedding) = prediction.embedding() {
+        dict.set_item("embedding", embedding)?;
+    }
+    Ok(dict.into())
+}
+
+fn tags_to_dict<'py>(py: Python<'py>, tags: &[Tag]) -> PyResult<&'py PyDict> {
+    let dict = PyDict::new(py);
+    for tag in tags {
+        dict.set_item(tag.name, tag.score)?;
+    }
+    Ok(dict)
+}
+
+fn to_py_err(err: Error) -> PyErr {
+    match err {
+        Error::Io(err) => err.into(),
+        err => PyValueError::new_err(err.to_string()),
+    }
+}
@@ -0,0 +1,79 @@
+use witchbooru::{
+    models::{Calibration, NaiveBayes, NeuralNet},
+    PixelFormat, Tag,
+};
+
+use js_sys::{ArrayBuffer, Uint8Array};
+use serde::Serialize;
+use std::io::Cursor;
+use wasm_bindgen::prelude::*;
+use web_sys::ImageData;
+
+#[wasm_bindgen]
+pub struct Classifier(witchbooru::Classifier);
+
+#[derive(Serialize)]
+struct Prediction<'a> {
+    general: &'a [Tag<'a>],
+    character: &'a [Tag<'a>],
+}
+
+#[wasm_bindgen]
+impl Classifier {
+    /// Loads the model from the contents of its files.
+    ///
+    /// `neural_net` is either `neural-net.onnx` or `neural-net.nnef.tar`, and `naive_bayes` is
+    /// either `naive-bayes.npz` or `naive-bayes.q8.bin`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        neural_net: &ArrayBuffer,
+        naive_bayes: &ArrayBuffer,
+        general_tags: &str,
+        character_tags: &str,
+        calibration: Option<ArrayBuffer>,
+        topk: usize,
+    ) -> Result<Classifier, JsValue> {
+        let neural_net = NeuralNet::from_bytes(&to_vec(neural_net)).map_err(to_js_error)?;
+        let naive_bayes = NaiveBayes::from_bytes(to_vec(naive_bayes)).map_err(to_js_error)?;
+
+        let calibration = calibration
+            .map(|calibration| Calibration::new(Cursor::new(to_vec(&calibration))))
+            .transpose()
+            .map_err(to_js_error)?;
+
+        let params = witchbooru::Params {
+            neural_net,
+            naive_bayes,
+            calibration,
+            general_tags: general_tags.lines().map(ToOwned::to_owned).collect(),
+            character_tags: character_tags.lines().map(ToOwned::to_owned).collect(),
+            topk,
+            augmentation: None,
+        };
+        witchbooru::Classifier::new(params)
+            .map(Self)
+            .map_err(to_js_error)
+    }
+
+    /// Returns `{ general, character }`, each of which is an array of `{ name, score }`.
+    pub fn predict(&self, img: &ImageData) -> Result<JsValue, JsValue> {
+        let data = img.data();
+        let prediction = self
+            .0
+            .predict_rgb(&data, img.width(), img.height(), PixelFormat::Rgba)
+            .map_err(to_js_error)?;
+        JsValue::from_serde(&Prediction {
+            general: prediction.general(),
+            character: prediction.character(),
+        })
+        .map_err(to_js_error)
+    }
+}
+
+fn to_vec(buffer: &ArrayBuffer) -> Vec<u8> {
+    Uint8Array::new(buffer).to_vec()
+}
+
+fn to_js_error<E: std::fmt::Display>(err: E) -> JsValue {
+    js_sys::Error::new(&err.to_string()).into()
+}